@@ -1,12 +1,8 @@
-use std::{convert::TryFrom, error::Error, fs};
+use std::error::Error;
 
 use clap::{arg, Command};
 use midir::{MidiOutput, MidiOutputConnection};
-use nodi::{
-	midly::{Format, Smf},
-	timers::Ticker,
-	Player, Sheet,
-};
+use nodi::{Player, Sheet};
 
 struct Args {
 	file: String,
@@ -47,17 +43,10 @@ impl Args {
 			return list_devices();
 		}
 
-		let data = fs::read(&self.file)?;
-		let Smf { header, tracks } = Smf::parse(&data)?;
-		let timer = Ticker::try_from(header.timing)?;
+		let (sheet, timer) = Sheet::open(&self.file)?;
 
 		let con = get_connection(self.device_no)?;
 
-		let sheet = match header.format {
-			Format::SingleTrack | Format::Sequential => Sheet::sequential(&tracks),
-			Format::Parallel => Sheet::parallel(&tracks),
-		};
-
 		let mut player = Player::new(timer, con);
 
 		println!("starting playback");
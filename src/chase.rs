@@ -0,0 +1,222 @@
+use midly::{
+	num::{u4, u7},
+	MidiMessage, PitchBend,
+};
+
+use crate::{Event, KeySignature, MidiEvent, TimeSignature, Timeline};
+
+// Controllers sent last, in this order, since the data entry controllers
+// apply to the last selected (N)RPN.
+const PARAMETER_CONTROLLERS: [u8; 6] = [99, 98, 101, 100, 6, 38];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct ChannelState {
+	program: Option<u7>,
+	controllers: [Option<u7>; 128],
+	pitch_bend: Option<PitchBend>,
+	pressure: Option<u7>,
+}
+
+impl Default for ChannelState {
+	fn default() -> Self {
+		Self {
+			program: None,
+			controllers: [None; 128],
+			pitch_bend: None,
+			pressure: None,
+		}
+	}
+}
+
+impl ChannelState {
+	fn update(&mut self, msg: MidiMessage) {
+		match msg {
+			MidiMessage::ProgramChange { program } => self.program = Some(program),
+			MidiMessage::PitchBend { bend } => self.pitch_bend = Some(bend),
+			MidiMessage::ChannelAftertouch { vel } => self.pressure = Some(vel),
+			// Reset All Controllers, as described in the MIDI recommended practice RP-015.
+			MidiMessage::Controller { controller, .. } if controller == 121 => {
+				self.pitch_bend = None;
+				self.pressure = None;
+				for cc in [1, 11, 64, 65, 66, 67, 98, 99, 100, 101] {
+					self.controllers[cc] = None;
+				}
+			}
+			// Channel mode messages are not part of the state.
+			MidiMessage::Controller { controller, value } if controller < 120 => {
+				self.controllers[controller.as_int() as usize] = Some(value);
+			}
+			_ => (),
+		}
+	}
+
+	fn events(&self, channel: u4, buf: &mut Vec<Event>) {
+		let midi = |message| Event::Midi(MidiEvent { channel, message });
+		let cc = |c: u8| {
+			self.controllers[c as usize].map(|value| {
+				midi(MidiMessage::Controller {
+					controller: c.into(),
+					value,
+				})
+			})
+		};
+
+		// Bank select has to precede the program change.
+		buf.extend(cc(0));
+		buf.extend(cc(32));
+		buf.extend(
+			self.program
+				.map(|program| midi(MidiMessage::ProgramChange { program })),
+		);
+		buf.extend(
+			(1..120)
+				.filter(|c| *c != 32 && !PARAMETER_CONTROLLERS.contains(c))
+				.filter_map(cc),
+		);
+		buf.extend(PARAMETER_CONTROLLERS.iter().filter_map(|c| cc(*c)));
+		buf.extend(
+			self.pitch_bend
+				.map(|bend| midi(MidiMessage::PitchBend { bend })),
+		);
+		buf.extend(
+			self.pressure
+				.map(|vel| midi(MidiMessage::ChannelAftertouch { vel })),
+		);
+	}
+}
+
+/// The state of a track at a given point: the last tempo, time and key
+/// signatures, the SysEx messages sent so far and every channel's program,
+/// controllers, pitch bend and pressure.
+///
+/// Starting playback from the middle of a track requires "chasing" this
+/// state; otherwise the notes would play with the wrong instruments and
+/// settings. [Player::play_from](crate::Player::play_from) does this for you.
+///
+/// # Notes
+/// Only the last value of every (N)RPN related controller is kept, so only
+/// the last selected parameter is restored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChaseState {
+	channels: [ChannelState; 16],
+	sysex: Vec<Event>,
+	tempo: Option<u32>,
+	time_sig: Option<TimeSignature>,
+	key_sig: Option<KeySignature>,
+}
+
+impl ChaseState {
+	/// Creates a blank [ChaseState].
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Returns the state of the given track right before `tick`.
+	///
+	/// Events at `tick` are not included.
+	pub fn at<'a, S: Into<Timeline<'a>>>(sheet: S, tick: u32) -> Self {
+		let mut state = Self::default();
+		for (_, moment) in sheet.into().iter().take_while(|(t, _)| *t < tick) {
+			for e in &moment.events {
+				state.update(e);
+			}
+		}
+		state
+	}
+
+	/// Updates the state with an [Event].
+	pub fn update(&mut self, event: &Event) {
+		match event {
+			Event::Midi(msg) => self.channels[msg.channel.as_int() as usize].update(msg.message),
+			Event::Tempo(t) => self.tempo = Some(*t),
+			Event::TimeSignature(..) => self.time_sig = event.as_time_signature(),
+			Event::KeySignature(..) => self.key_sig = event.as_key_signature(),
+			Event::SysEx(_) | Event::Escape(_) => self.sysex.push(event.clone()),
+			Event::Text(..) => (),
+		}
+	}
+
+	/// Returns the last tempo, if any.
+	pub fn tempo(&self) -> Option<u32> {
+		self.tempo
+	}
+
+	/// Returns the last time signature, if any.
+	pub fn time_signature(&self) -> Option<TimeSignature> {
+		self.time_sig
+	}
+
+	/// Returns the last key signature, if any.
+	pub fn key_signature(&self) -> Option<KeySignature> {
+		self.key_sig
+	}
+
+	/// Returns the events that recreate this state, in the order they should
+	/// be played.
+	///
+	/// The tempo, time and key signatures come first, followed by every SysEx
+	/// message (since they often reset the device), followed by the state of
+	/// every channel.
+	pub fn events(&self) -> Vec<Event> {
+		let mut buf = Vec::new();
+		buf.extend(self.tempo.map(Event::Tempo));
+		buf.extend(self.time_sig.map(|t| {
+			Event::TimeSignature(
+				t.numerator,
+				t.denominator,
+				t.clocks_per_click,
+				t.notes_per_quarter,
+			)
+		}));
+		buf.extend(self.key_sig.map(|k| Event::KeySignature(k.sharps, k.minor)));
+		buf.extend(self.sysex.iter().cloned());
+		for (i, ch) in self.channels.iter().enumerate() {
+			ch.events(u4::new(i as u8), &mut buf);
+		}
+		buf
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Moment, Sheet};
+
+	#[test]
+	fn chase() {
+		let cc = |controller: u8, value: u8| {
+			Event::Midi(MidiEvent {
+				channel: u4::new(1),
+				message: MidiMessage::Controller {
+					controller: controller.into(),
+					value: value.into(),
+				},
+			})
+		};
+		let program = Event::Midi(MidiEvent {
+			channel: u4::new(1),
+			message: MidiMessage::ProgramChange { program: 40.into() },
+		});
+
+		let mut sheet = Sheet::new();
+		sheet.push(vec![Event::Tempo(400_000), cc(7, 100), program.clone(), cc(0, 8)].into());
+		sheet.push(vec![cc(1, 50), cc(7, 90)].into());
+		sheet.push(vec![cc(121, 0), Event::Tempo(300_000)].into());
+		sheet.push(Moment::default());
+
+		assert_eq!(
+			ChaseState::at(&sheet, 2).events(),
+			vec![
+				Event::Tempo(400_000),
+				cc(0, 8),
+				program.clone(),
+				cc(1, 50),
+				cc(7, 90)
+			]
+		);
+		assert_eq!(
+			ChaseState::at(&sheet, 3).events(),
+			vec![Event::Tempo(300_000), cc(0, 8), program, cc(7, 90)]
+		);
+	}
+}
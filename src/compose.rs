@@ -1,6 +1,16 @@
 //! Contains various small types that implement [Connection] that add extra capabilities to another [Connection] by wrapping them.
 
-use crate::{Connection, MidiEvent};
+mod mixer;
+mod router;
+mod velocity;
+
+use midly::live::{SystemCommon, SystemRealtime};
+
+use crate::{Connection, MidiEvent, TextKind};
+
+pub use mixer::{Mixer, MixerHandle, Strip};
+pub use router::{Route, Router, RouterHandle};
+pub use velocity::{Curve, Velocity};
 
 /// [Connection] combinators.
 ///
@@ -21,6 +31,36 @@ pub trait Compose: Connection + Sized {
 	{
 		Filter { con: self, f }
 	}
+
+	/// Returns a [Connection] that plays its input on both `self` and `other`.
+	///
+	/// By default, the returned connection returns `false` if any of the two
+	/// does; see [Tee::stop_when].
+	fn tee<C: Connection>(self, other: C) -> Tee<Self, C> {
+		Tee {
+			first: self,
+			second: other,
+			stop_when: StopWhen::Any,
+		}
+	}
+
+	/// Returns a [Connection] that remaps, duplicates and splits channels
+	/// according to `routes`, see [Router].
+	fn route(self, routes: Vec<Route>) -> Router<Self> {
+		Router::new(self, routes)
+	}
+
+	/// Returns a [Connection] that applies `curve` to the velocity of the
+	/// `NoteOn` messages, see [Velocity].
+	fn velocity(self, curve: Curve) -> Velocity<Self> {
+		Velocity::new(self, curve)
+	}
+
+	/// Returns a [Connection] whose channels can be muted, soloed and have
+	/// their volume and pan overridden at runtime, see [Mixer].
+	fn mixer(self) -> Mixer<Self> {
+		Mixer::new(self)
+	}
 }
 
 impl<C: Connection> Compose for C {}
@@ -39,6 +79,26 @@ impl<C: Connection, F: FnMut(MidiEvent) -> MidiEvent> Connection for Map<C, F> {
 		let e = (self.f)(event);
 		self.con.play(e)
 	}
+
+	fn send_sys_rt(&mut self, msg: SystemRealtime) {
+		self.con.send_sys_rt(msg);
+	}
+
+	fn send_sys_common(&mut self, msg: SystemCommon<'_>) {
+		self.con.send_sys_common(msg);
+	}
+
+	fn send_sys_ex(&mut self, data: &[u8]) {
+		self.con.send_sys_ex(data);
+	}
+
+	fn send_escape(&mut self, data: &[u8]) {
+		self.con.send_escape(data);
+	}
+
+	fn send_text(&mut self, kind: TextKind, text: &[u8]) {
+		self.con.send_text(kind, text);
+	}
 }
 
 /// A filtering [Connection]. Created by calling [Compose::filter] on an existing connection.
@@ -61,4 +121,270 @@ where
 			true
 		}
 	}
+
+	fn send_sys_rt(&mut self, msg: SystemRealtime) {
+		self.con.send_sys_rt(msg);
+	}
+
+	fn send_sys_common(&mut self, msg: SystemCommon<'_>) {
+		self.con.send_sys_common(msg);
+	}
+
+	fn send_sys_ex(&mut self, data: &[u8]) {
+		self.con.send_sys_ex(data);
+	}
+
+	fn send_escape(&mut self, data: &[u8]) {
+		self.con.send_escape(data);
+	}
+
+	fn send_text(&mut self, kind: TextKind, text: &[u8]) {
+		self.con.send_text(kind, text);
+	}
+}
+
+/// Decides what a [Connection] that plays on several connections returns
+/// from [Connection::play], see [Tee] and [Fanout].
+///
+/// Every connection receives the event regardless.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StopWhen {
+	/// Return `false` if any of the connections returns `false`.
+	Any,
+	/// Return `false` only if every connection returns `false`.
+	All,
+	/// Always return `true`.
+	Never,
+}
+
+impl StopWhen {
+	// Combines the results of the connections; `results` is consumed to the
+	// end, so every connection plays the event.
+	fn keep_playing<I: IntoIterator<Item = bool>>(self, results: I) -> bool {
+		let mut any = false;
+		let mut all = true;
+		let mut empty = true;
+		for ok in results {
+			any |= ok;
+			all &= ok;
+			empty = false;
+		}
+		match self {
+			Self::Any => all,
+			Self::All => any || empty,
+			Self::Never => true,
+		}
+	}
+}
+
+/// A [Connection] that plays on two connections. Created by calling
+/// [Compose::tee] on an existing connection.
+///
+/// Every [MidiEvent], system realtime, system common, SysEx and text message
+/// is sent to both connections. Nest it to drive more connections, or use a
+/// [Fanout].
+#[derive(Clone, Debug)]
+pub struct Tee<A: Connection, B: Connection> {
+	/// The first wrapped [Connection].
+	pub first: A,
+	/// The second wrapped [Connection].
+	pub second: B,
+	/// What [Connection::play] returns when a connection returns `false`.
+	///
+	/// The default value is [StopWhen::Any].
+	pub stop_when: StopWhen,
+}
+
+impl<A: Connection, B: Connection> Connection for Tee<A, B> {
+	fn play(&mut self, event: MidiEvent) -> bool {
+		let results = [self.first.play(event), self.second.play(event)];
+		self.stop_when.keep_playing(results)
+	}
+
+	fn send_sys_rt(&mut self, msg: SystemRealtime) {
+		self.first.send_sys_rt(msg);
+		self.second.send_sys_rt(msg);
+	}
+
+	fn send_sys_common(&mut self, msg: SystemCommon<'_>) {
+		self.first.send_sys_common(msg);
+		self.second.send_sys_common(msg);
+	}
+
+	fn send_sys_ex(&mut self, data: &[u8]) {
+		self.first.send_sys_ex(data);
+		self.second.send_sys_ex(data);
+	}
+
+	fn send_escape(&mut self, data: &[u8]) {
+		self.first.send_escape(data);
+		self.second.send_escape(data);
+	}
+
+	fn send_text(&mut self, kind: TextKind, text: &[u8]) {
+		self.first.send_text(kind, text);
+		self.second.send_text(kind, text);
+	}
+
+	fn all_notes_off(&mut self) {
+		self.first.all_notes_off();
+		self.second.all_notes_off();
+	}
+}
+
+/// A [Connection] that plays on any number of connections, chosen at
+/// runtime.
+///
+/// This is the dynamic version of [Tee].
+///
+/// # Examples
+/// ```
+/// use nodi::compose::{Fanout, StopWhen};
+///
+/// # struct Device;
+/// # impl nodi::Connection for Device { fn play(&mut self, _: nodi::MidiEvent) -> bool { true } }
+/// let mut out = Fanout::new();
+/// out.push(Device);
+/// out.push(Device);
+/// // Keep playing as long as one of the devices is still there.
+/// out.stop_when = StopWhen::All;
+/// ```
+pub struct Fanout {
+	/// The wrapped connections.
+	pub cons: Vec<Box<dyn Connection + Send>>,
+	/// What [Connection::play] returns when a connection returns `false`.
+	///
+	/// The default value is [StopWhen::Any].
+	pub stop_when: StopWhen,
+}
+
+impl Default for Fanout {
+	fn default() -> Self {
+		Self {
+			cons: Vec::new(),
+			stop_when: StopWhen::Any,
+		}
+	}
+}
+
+impl Fanout {
+	/// Creates a [Fanout] with no connections.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds a connection.
+	pub fn push<C: Connection + Send + 'static>(&mut self, con: C) {
+		self.cons.push(Box::new(con));
+	}
+}
+
+impl From<Vec<Box<dyn Connection + Send>>> for Fanout {
+	fn from(cons: Vec<Box<dyn Connection + Send>>) -> Self {
+		Self {
+			cons,
+			..Self::default()
+		}
+	}
+}
+
+impl std::fmt::Debug for Fanout {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Fanout")
+			.field("cons", &self.cons.len())
+			.field("stop_when", &self.stop_when)
+			.finish()
+	}
+}
+
+impl Connection for Fanout {
+	fn play(&mut self, event: MidiEvent) -> bool {
+		self.stop_when
+			.keep_playing(self.cons.iter_mut().map(|c| c.play(event)))
+	}
+
+	fn send_sys_rt(&mut self, msg: SystemRealtime) {
+		for c in &mut self.cons {
+			c.send_sys_rt(msg);
+		}
+	}
+
+	fn send_sys_common(&mut self, msg: SystemCommon<'_>) {
+		for c in &mut self.cons {
+			c.send_sys_common(msg);
+		}
+	}
+
+	fn send_sys_ex(&mut self, data: &[u8]) {
+		for c in &mut self.cons {
+			c.send_sys_ex(data);
+		}
+	}
+
+	fn send_escape(&mut self, data: &[u8]) {
+		for c in &mut self.cons {
+			c.send_escape(data);
+		}
+	}
+
+	fn send_text(&mut self, kind: TextKind, text: &[u8]) {
+		for c in &mut self.cons {
+			c.send_text(kind, text);
+		}
+	}
+
+	fn all_notes_off(&mut self) {
+		for c in &mut self.cons {
+			c.all_notes_off();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use midly::MidiMessage;
+
+	use super::*;
+
+	// Records the events, returns false once it has played `limit` of them.
+	struct Limited {
+		played: usize,
+		limit: usize,
+	}
+
+	impl Connection for Limited {
+		fn play(&mut self, _: MidiEvent) -> bool {
+			self.played += 1;
+			self.played < self.limit
+		}
+	}
+
+	#[test]
+	fn fan_out() {
+		let event = MidiEvent {
+			channel: 0.into(),
+			message: MidiMessage::NoteOn {
+				key: 60.into(),
+				vel: 100.into(),
+			},
+		};
+		let limited = |limit| Limited { played: 0, limit };
+
+		let mut tee = limited(1).tee(limited(3));
+		assert!(!tee.play(event));
+		// Both connections receive the event, even if the first one stops.
+		assert_eq!((tee.first.played, tee.second.played), (1, 1));
+		tee.stop_when = StopWhen::All;
+		assert!(tee.play(event));
+		assert!(!tee.play(event));
+
+		let mut fanout = Fanout::from(vec![
+			Box::new(limited(1)) as Box<dyn Connection + Send>,
+			Box::new(limited(2)),
+		]);
+		fanout.stop_when = StopWhen::Never;
+		assert!(fanout.play(event));
+		assert!(fanout.play(event));
+		assert!(Fanout::new().play(event));
+	}
 }
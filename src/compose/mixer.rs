@@ -0,0 +1,313 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use midly::{
+	live::{SystemCommon, SystemRealtime},
+	num::{u4, u7},
+	MidiMessage,
+};
+
+use crate::{ActiveNotes, Connection, MidiEvent, TextKind};
+
+// Controller numbers.
+const VOLUME: u8 = 7;
+const PAN: u8 = 10;
+// The values restored when the track sets no volume or pan, as General MIDI
+// devices reset them to.
+const DEFAULT_VOLUME: u8 = 100;
+const DEFAULT_PAN: u8 = 64;
+
+/// The settings of a channel of a [Mixer].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Strip {
+	/// Silences the channel.
+	pub mute: bool,
+	/// Silences every channel that is not soloed, if any channel is soloed.
+	pub solo: bool,
+	/// Overrides the volume (controller 7) of the channel.
+	pub volume: Option<u7>,
+	/// Overrides the pan (controller 10) of the channel.
+	pub pan: Option<u7>,
+}
+
+/// A handle to change the settings of a [Mixer] while it's in use, for
+/// example while a [Player](crate::Player) plays on another thread.
+///
+/// This `struct` is obtained with [Mixer::handle]; it can be cloned and sent to
+/// other threads.
+#[derive(Clone, Debug, Default)]
+pub struct MixerHandle(Arc<Mutex<[Strip; 16]>>);
+
+impl MixerHandle {
+	fn lock(&self) -> MutexGuard<'_, [Strip; 16]> {
+		// A panic while holding the lock cannot leave the strips half-updated.
+		self.0.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	fn update<F: FnOnce(&mut Strip)>(&self, channel: u4, f: F) {
+		f(&mut self.lock()[channel.as_int() as usize]);
+	}
+
+	/// Returns the settings of the given channel.
+	pub fn strip(&self, channel: u4) -> Strip {
+		self.lock()[channel.as_int() as usize]
+	}
+
+	/// Replaces the settings of the given channel.
+	pub fn set_strip(&self, channel: u4, strip: Strip) {
+		self.update(channel, |s| *s = strip);
+	}
+
+	/// Mutes or unmutes the given channel.
+	pub fn set_mute(&self, channel: u4, mute: bool) {
+		self.update(channel, |s| s.mute = mute);
+	}
+
+	/// Solos or unsolos the given channel.
+	pub fn set_solo(&self, channel: u4, solo: bool) {
+		self.update(channel, |s| s.solo = solo);
+	}
+
+	/// Overrides the volume of the given channel, `None` restores the volume
+	/// of the track.
+	pub fn set_volume(&self, channel: u4, volume: Option<u7>) {
+		self.update(channel, |s| s.volume = volume);
+	}
+
+	/// Overrides the pan of the given channel, `None` restores the pan of the
+	/// track.
+	pub fn set_pan(&self, channel: u4, pan: Option<u7>) {
+		self.update(channel, |s| s.pan = pan);
+	}
+
+	/// Resets every channel to the default settings.
+	pub fn reset(&self) {
+		*self.lock() = Default::default();
+	}
+}
+
+/// A [Connection] that mutes, solos and overrides the volume and pan of
+/// channels. Created by calling [Compose::mixer](super::Compose::mixer) on
+/// an existing connection.
+///
+/// The settings are changed through a [MixerHandle], from any thread. They
+/// take effect when the next event is played, or when
+/// [update](Self::update) is called:
+/// - The notes sounding on channels that become silent are turned off.
+/// - The overridden volumes and pans are sent, and the ones of the track are
+///   restored when an override is removed; if the track has not set them, the
+///   defaults (a volume of 100 and a centered pan) are sent.
+///
+/// Notes of silent channels are dropped; the other messages (program
+/// changes, controllers...) are still sent, so that the channel sounds right
+/// once it's audible again.
+///
+/// # Examples
+/// ```
+/// use nodi::compose::Compose;
+///
+/// # struct Device;
+/// # impl nodi::Connection for Device { fn play(&mut self, _: nodi::MidiEvent) -> bool { true } }
+/// let mixer = Device.mixer();
+/// let handle = mixer.handle();
+/// // From any thread, while playing:
+/// handle.set_mute(9.into(), true);
+/// handle.set_volume(0.into(), Some(100.into()));
+/// ```
+#[derive(Debug)]
+pub struct Mixer<C: Connection> {
+	/// The wrapped [Connection].
+	pub con: C,
+	handle: MixerHandle,
+	// The settings that are in effect.
+	applied: [Strip; 16],
+	// The volume and pan of the track, per channel.
+	volumes: [Option<u7>; 16],
+	pans: [Option<u7>; 16],
+	notes: ActiveNotes,
+}
+
+impl<C: Connection> Mixer<C> {
+	/// Creates a [Mixer] with every channel audible and nothing overridden.
+	pub fn new(con: C) -> Self {
+		Self {
+			con,
+			handle: MixerHandle::default(),
+			applied: Default::default(),
+			volumes: [None; 16],
+			pans: [None; 16],
+			notes: ActiveNotes::new(),
+		}
+	}
+
+	/// Returns a handle to change the settings.
+	pub fn handle(&self) -> MixerHandle {
+		self.handle.clone()
+	}
+
+	/// Applies the settings changed through the [MixerHandle].
+	///
+	/// This is done automatically before playing every event.
+	pub fn update(&mut self) {
+		let strips = *self.handle.lock();
+		if strips == self.applied {
+			return;
+		}
+
+		for ch in 0..16 {
+			let channel = u4::new(ch as u8);
+			if audible(&self.applied, ch) && !audible(&strips, ch) {
+				for e in self.notes.release_channel(channel) {
+					self.con.play(e);
+				}
+			}
+
+			let (old, new) = (self.applied[ch], strips[ch]);
+			if old.volume != new.volume {
+				let value = new
+					.volume
+					.or(self.volumes[ch])
+					.unwrap_or(DEFAULT_VOLUME.into());
+				self.con.play(cc(channel, VOLUME, value));
+			}
+			if old.pan != new.pan {
+				let value = new.pan.or(self.pans[ch]).unwrap_or(DEFAULT_PAN.into());
+				self.con.play(cc(channel, PAN, value));
+			}
+		}
+
+		self.applied = strips;
+	}
+}
+
+// Returns whether the channel `ch` can be heard.
+fn audible(strips: &[Strip; 16], ch: usize) -> bool {
+	let solo = strips.iter().any(|s| s.solo);
+	!strips[ch].mute && (!solo || strips[ch].solo)
+}
+
+fn cc(channel: u4, controller: u8, value: u7) -> MidiEvent {
+	MidiEvent {
+		channel,
+		message: MidiMessage::Controller {
+			controller: controller.into(),
+			value,
+		},
+	}
+}
+
+impl<C: Connection> Connection for Mixer<C> {
+	fn play(&mut self, mut event: MidiEvent) -> bool {
+		self.update();
+		let ch = event.channel.as_int() as usize;
+		let strip = self.applied[ch];
+
+		match &mut event.message {
+			MidiMessage::NoteOn { .. }
+			| MidiMessage::NoteOff { .. }
+			| MidiMessage::Aftertouch { .. }
+				if !audible(&self.applied, ch) =>
+			{
+				return true;
+			}
+			MidiMessage::Controller { controller, value } if *controller == VOLUME => {
+				self.volumes[ch] = Some(*value);
+				*value = strip.volume.unwrap_or(*value);
+			}
+			MidiMessage::Controller { controller, value } if *controller == PAN => {
+				self.pans[ch] = Some(*value);
+				*value = strip.pan.unwrap_or(*value);
+			}
+			_ => (),
+		}
+
+		self.notes.update(&event);
+		self.con.play(event)
+	}
+
+	fn send_sys_rt(&mut self, msg: SystemRealtime) {
+		self.con.send_sys_rt(msg);
+	}
+
+	fn send_sys_common(&mut self, msg: SystemCommon<'_>) {
+		self.con.send_sys_common(msg);
+	}
+
+	fn send_sys_ex(&mut self, data: &[u8]) {
+		self.con.send_sys_ex(data);
+	}
+
+	fn send_escape(&mut self, data: &[u8]) {
+		self.con.send_escape(data);
+	}
+
+	fn send_text(&mut self, kind: TextKind, text: &[u8]) {
+		self.con.send_text(kind, text);
+	}
+
+	fn all_notes_off(&mut self) {
+		self.notes = ActiveNotes::new();
+		self.con.all_notes_off();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_support::{self, off, Capture};
+
+	#[test]
+	fn mixer() {
+		let on = |ch: u8, key: u8| test_support::on(ch, key, 100);
+		let control = |ch: u8, controller: u8, value: u8| cc(ch.into(), controller, value.into());
+
+		let mut mixer = Mixer::new(Capture::default());
+		let handle = mixer.handle();
+		mixer.play(control(1, VOLUME, 90));
+		mixer.play(on(0, 60));
+		mixer.play(on(1, 62));
+		mixer.con.0.clear();
+
+		// Soloing channel 0 releases the note of channel 1.
+		handle.set_solo(0.into(), true);
+		handle.set_volume(1.into(), Some(50.into()));
+		mixer.play(on(1, 64));
+		mixer.play(control(1, VOLUME, 80));
+		assert_eq!(
+			mixer.con.0.drain(..).collect::<Vec<_>>(),
+			[
+				off(1, 62),
+				control(1, 123, 0),
+				control(1, VOLUME, 50),
+				control(1, VOLUME, 50)
+			]
+		);
+
+		// The volume of the track is restored.
+		handle.reset();
+		mixer.play(on(1, 64));
+		assert_eq!(mixer.con.0, [control(1, VOLUME, 80), on(1, 64)]);
+	}
+
+	#[test]
+	fn default_restored() {
+		let control = |ch: u8, controller: u8, value: u8| cc(ch.into(), controller, value.into());
+
+		let mut mixer = Mixer::new(Capture::default());
+		let handle = mixer.handle();
+		handle.set_volume(2.into(), Some(30.into()));
+		handle.set_pan(2.into(), Some(0.into()));
+		mixer.update();
+		// The track never set them, the defaults are restored.
+		handle.reset();
+		mixer.update();
+		assert_eq!(
+			mixer.con.0,
+			[
+				control(2, VOLUME, 30),
+				control(2, PAN, 0),
+				control(2, VOLUME, DEFAULT_VOLUME),
+				control(2, PAN, DEFAULT_PAN),
+			]
+		);
+	}
+}
@@ -0,0 +1,328 @@
+use std::{
+	ops::RangeInclusive,
+	sync::{Arc, Mutex, MutexGuard},
+};
+
+use midly::{
+	live::{SystemCommon, SystemRealtime},
+	num::u4,
+	MidiMessage,
+};
+
+use crate::{Connection, MidiEvent, TextKind};
+
+/// A routing rule of a [Router].
+///
+/// Events coming from channel `from` are sent to channel `to`. Note messages
+/// are routed only if their key is in `keys`, and `NoteOn` messages only if
+/// their velocity is in `velocities`; the other channel messages
+/// (controllers, program changes, pitch bends...) are routed regardless.
+///
+/// # Examples
+/// ```
+/// use nodi::compose::Route;
+///
+/// // A keyboard split at middle C: the left hand plays on channel 2.
+/// let left = Route::new(0.into(), 2.into()).with_keys(0..=59);
+/// let right = Route::new(0.into(), 0.into()).with_keys(60..=127);
+/// // A velocity layer: hard hits also trigger channel 3.
+/// let layer = Route::new(0.into(), 3.into()).with_velocities(100..=127);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Route {
+	/// The input channel.
+	pub from: u4,
+	/// The output channel.
+	pub to: u4,
+	/// The keys this route applies to.
+	pub keys: RangeInclusive<u8>,
+	/// The velocities of `NoteOn` messages this route applies to.
+	pub velocities: RangeInclusive<u8>,
+}
+
+impl Route {
+	/// Creates a [Route] from channel `from` to channel `to`, for every key and
+	/// velocity.
+	pub fn new(from: u4, to: u4) -> Self {
+		Self {
+			from,
+			to,
+			keys: 0..=127,
+			velocities: 0..=127,
+		}
+	}
+
+	/// Restricts the route to the given keys.
+	pub fn with_keys(mut self, keys: RangeInclusive<u8>) -> Self {
+		self.keys = keys;
+		self
+	}
+
+	/// Restricts the route to `NoteOn` messages with the given velocities.
+	pub fn with_velocities(mut self, velocities: RangeInclusive<u8>) -> Self {
+		self.velocities = velocities;
+		self
+	}
+}
+
+/// A handle to change the routes of a [Router] while it's in use, for example
+/// while a [Player](crate::Player) plays on another thread.
+///
+/// This `struct` is obtained with [Router::handle]; it can be cloned and sent
+/// to other threads.
+#[derive(Clone, Debug, Default)]
+pub struct RouterHandle(Arc<Mutex<Vec<Route>>>);
+
+impl RouterHandle {
+	fn lock(&self) -> MutexGuard<'_, Vec<Route>> {
+		// A panic while holding the lock cannot leave the routes half-updated.
+		self.0.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	/// Returns the current routes.
+	pub fn routes(&self) -> Vec<Route> {
+		self.lock().clone()
+	}
+
+	/// Replaces every route.
+	pub fn set(&self, routes: Vec<Route>) {
+		*self.lock() = routes;
+	}
+
+	/// Adds a route.
+	pub fn push(&self, route: Route) {
+		self.lock().push(route);
+	}
+
+	/// Removes the routes of the input channel `from`, so that it's played
+	/// unchanged again.
+	pub fn remove(&self, from: u4) {
+		self.lock().retain(|r| r.from != from);
+	}
+
+	/// Removes every route.
+	pub fn clear(&self) {
+		self.lock().clear();
+	}
+}
+
+/// A [Connection] that remaps, duplicates and splits channels. Created by
+/// calling [Compose::route](super::Compose::route) on an existing connection.
+///
+/// Every event is sent once per matching [Route] of its channel, so several
+/// routes from the same channel duplicate it. Channels without any route are
+/// played unchanged; events of a routed channel that match none of its
+/// routes, such as notes outside every split, are dropped.
+///
+/// A `NoteOff` is sent where its `NoteOn` was, even if the routes have changed
+/// in the meantime, so that no note is left stuck.
+///
+/// # Examples
+/// ```
+/// use nodi::compose::{Compose, Route};
+///
+/// # struct Device;
+/// # impl nodi::Connection for Device { fn play(&mut self, _: nodi::MidiEvent) -> bool { true } }
+/// let router = Device.route(vec![
+///     Route::new(0.into(), 2.into()).with_keys(0..=59),
+///     Route::new(0.into(), 0.into()).with_keys(60..=127),
+/// ]);
+/// let handle = router.handle();
+/// // Later, even while playing: move the split point.
+/// handle.set(vec![
+///     Route::new(0.into(), 2.into()).with_keys(0..=47),
+///     Route::new(0.into(), 0.into()).with_keys(48..=127),
+/// ]);
+/// ```
+#[derive(Debug)]
+pub struct Router<C: Connection> {
+	/// The wrapped [Connection].
+	pub con: C,
+	routes: RouterHandle,
+	// The output channels of every sounding note, as a bit set.
+	sounding: [[u16; 128]; 16],
+}
+
+impl<C: Connection> Router<C> {
+	/// Creates a [Router] with the given routes.
+	pub fn new(con: C, routes: Vec<Route>) -> Self {
+		Self {
+			con,
+			routes: RouterHandle(Arc::new(Mutex::new(routes))),
+			sounding: [[0; 128]; 16],
+		}
+	}
+
+	/// Returns a handle to change the routes.
+	pub fn handle(&self) -> RouterHandle {
+		self.routes.clone()
+	}
+
+	// Returns the output channels of an event from `channel`, or None if it's played unchanged.
+	fn targets(&mut self, channel: u4, message: &MidiMessage) -> Option<u16> {
+		let sounding = &mut self.sounding[channel.as_int() as usize];
+		// Sounding notes are followed first, their channel may not be routed anymore.
+		match *message {
+			MidiMessage::NoteOn { key, vel } if vel == 0 => {
+				Some(std::mem::take(&mut sounding[key.as_int() as usize])).filter(|m| *m != 0)
+			}
+			MidiMessage::NoteOff { key, .. } => {
+				Some(std::mem::take(&mut sounding[key.as_int() as usize])).filter(|m| *m != 0)
+			}
+			MidiMessage::Aftertouch { key, .. } => {
+				Some(sounding[key.as_int() as usize]).filter(|m| *m != 0)
+			}
+			_ => None,
+		}
+		.or_else(|| self.route(channel, message))
+	}
+
+	// Returns the output channels of an event from `channel` according to the routes.
+	fn route(&mut self, channel: u4, message: &MidiMessage) -> Option<u16> {
+		let guard = self.routes.lock();
+		let routes = || guard.iter().filter(|r| r.from == channel);
+		if routes().next().is_none() {
+			// Played unchanged, but followed in case a route is added before the NoteOff.
+			if let MidiMessage::NoteOn { key, vel } = *message {
+				if vel > 0 {
+					self.sounding[channel.as_int() as usize][key.as_int() as usize] |=
+						1 << channel.as_int();
+				}
+			}
+			return None;
+		}
+
+		let bits = |r: &Route| 1 << r.to.as_int();
+		let matching = |key: u8, vel: Option<u8>| {
+			routes()
+				.filter(|r| r.keys.contains(&key))
+				.filter(|r| vel.is_none_or(|v| r.velocities.contains(&v)))
+				.fold(0, |mask, r| mask | bits(r))
+		};
+
+		Some(match *message {
+			MidiMessage::NoteOn { key, vel } if vel > 0 => {
+				let mask = matching(key.as_int(), Some(vel.as_int()));
+				self.sounding[channel.as_int() as usize][key.as_int() as usize] |= mask;
+				mask
+			}
+			MidiMessage::NoteOn { key, .. }
+			| MidiMessage::NoteOff { key, .. }
+			| MidiMessage::Aftertouch { key, .. } => matching(key.as_int(), None),
+			_ => routes().fold(0, |mask, r| mask | bits(r)),
+		})
+	}
+}
+
+impl<C: Connection> Connection for Router<C> {
+	fn play(&mut self, event: MidiEvent) -> bool {
+		let Some(mask) = self.targets(event.channel, &event.message) else {
+			return self.con.play(event);
+		};
+
+		let mut ok = true;
+		for ch in (0..16).filter(|ch| mask & (1 << ch) != 0) {
+			ok &= self.con.play(MidiEvent {
+				channel: u4::new(ch),
+				..event
+			});
+		}
+		ok
+	}
+
+	fn send_sys_rt(&mut self, msg: SystemRealtime) {
+		self.con.send_sys_rt(msg);
+	}
+
+	fn send_sys_common(&mut self, msg: SystemCommon<'_>) {
+		self.con.send_sys_common(msg);
+	}
+
+	fn send_sys_ex(&mut self, data: &[u8]) {
+		self.con.send_sys_ex(data);
+	}
+
+	fn send_escape(&mut self, data: &[u8]) {
+		self.con.send_escape(data);
+	}
+
+	fn send_text(&mut self, kind: TextKind, text: &[u8]) {
+		self.con.send_text(kind, text);
+	}
+
+	fn all_notes_off(&mut self) {
+		self.sounding = [[0; 128]; 16];
+		self.con.all_notes_off();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_support::{midi, off, on, Capture};
+
+	#[test]
+	fn route() {
+		let mut router = Router::new(
+			Capture::default(),
+			vec![
+				Route::new(0.into(), 2.into()).with_keys(0..=59),
+				Route::new(0.into(), 0.into()).with_keys(60..=127),
+				Route::new(0.into(), 3.into())
+					.with_keys(60..=127)
+					.with_velocities(100..=127),
+				Route::new(1.into(), 5.into()),
+			],
+		);
+		let program = |ch| midi(ch, MidiMessage::ProgramChange { program: 1.into() });
+
+		for e in [
+			program(0),
+			on(0, 40, 80),
+			on(0, 70, 80),
+			on(0, 72, 110),
+			on(1, 60, 80),
+			on(9, 36, 80),
+		] {
+			router.play(e);
+		}
+		assert_eq!(
+			router.con.0.drain(..).collect::<Vec<_>>(),
+			[
+				program(0),
+				program(2),
+				program(3),
+				on(2, 40, 80),
+				on(0, 70, 80),
+				on(0, 72, 110),
+				on(3, 72, 110),
+				on(5, 60, 80),
+				on(9, 36, 80),
+			]
+		);
+
+		// The note offs follow their notes even after the routes change.
+		router.handle().remove(0.into());
+		router.play(off(0, 72));
+		router.play(off(0, 40));
+		router.play(off(0, 41));
+		assert_eq!(
+			router.con.0,
+			[off(0, 72), off(3, 72), off(2, 40), off(0, 41)]
+		);
+	}
+
+	#[test]
+	fn route_added_while_sounding() {
+		let mut router = Router::new(Capture::default(), Vec::new());
+		router.play(on(0, 60, 100));
+		router.handle().push(Route::new(0.into(), 2.into()));
+		router.play(off(0, 60));
+		router.play(on(0, 62, 100));
+		router.play(off(0, 62));
+		assert_eq!(
+			router.con.0,
+			[on(0, 60, 100), off(0, 60), on(2, 62, 100), off(2, 62)]
+		);
+	}
+}
@@ -0,0 +1,165 @@
+use midly::{
+	live::{SystemCommon, SystemRealtime},
+	num::{u4, u7},
+	MidiMessage,
+};
+
+use crate::{Connection, MidiEvent, TextKind};
+
+/// A velocity curve, applied to `NoteOn` messages by a [Velocity]
+/// connection.
+///
+/// The result is always between 1 and 127, so that a `NoteOn` never turns
+/// into a `NoteOff`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Curve {
+	/// Multiplies the velocity by the given factor.
+	Scale(f32),
+	/// Raises the velocity, as a fraction of 127, to the given power; values
+	/// below `1.0` make soft notes louder, values above `1.0` make them
+	/// softer.
+	Exponential(f32),
+	/// Looks up the new velocity in a table, indexed by the velocity.
+	Table(Box<[u8; 128]>),
+	/// Replaces every velocity with the given value.
+	Fixed(u8),
+	/// Clamps the velocity between `min` and `max`, inclusive.
+	Clamp {
+		/// The lowest velocity.
+		min: u8,
+		/// The highest velocity.
+		max: u8,
+	},
+}
+
+impl Curve {
+	/// Applies the curve to the velocity of a `NoteOn` message.
+	pub fn apply(&self, vel: u7) -> u7 {
+		let v = vel.as_int();
+		let out = match self {
+			Self::Scale(factor) => (v as f32 * factor).round() as u8,
+			Self::Exponential(power) => ((v as f32 / 127.0).powf(*power) * 127.0).round() as u8,
+			Self::Table(table) => table[v as usize],
+			Self::Fixed(val) => *val,
+			Self::Clamp { min, max } => v.max(*min).min(*max),
+		};
+		u7::new(out.clamp(1, 127))
+	}
+}
+
+/// A [Connection] that applies velocity curves to `NoteOn` messages, per
+/// channel. Created by calling [Compose::velocity](super::Compose::velocity)
+/// on an existing connection.
+///
+/// A `NoteOn` with a velocity of 0 is a `NoteOff` and is left unchanged, like
+/// every other message.
+///
+/// # Examples
+/// ```
+/// use nodi::compose::{Compose, Curve};
+///
+/// # struct Device;
+/// # impl nodi::Connection for Device { fn play(&mut self, _: nodi::MidiEvent) -> bool { true } }
+/// // Soft notes are too quiet on this synth.
+/// let mut con = Device.velocity(Curve::Exponential(0.7));
+/// // But the drums should not go above 100.
+/// con.set_curve(9.into(), Some(Curve::Clamp { min: 1, max: 100 }));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Velocity<C: Connection> {
+	/// The wrapped [Connection].
+	pub con: C,
+	curves: [Option<Curve>; 16],
+}
+
+impl<C: Connection> Velocity<C> {
+	/// Creates a [Velocity] that applies `curve` on every channel.
+	pub fn new(con: C, curve: Curve) -> Self {
+		Self {
+			con,
+			curves: std::array::from_fn(|_| Some(curve.clone())),
+		}
+	}
+
+	/// Returns the curve of the given channel.
+	pub fn curve(&self, channel: u4) -> Option<&Curve> {
+		self.curves[channel.as_int() as usize].as_ref()
+	}
+
+	/// Sets the curve of the given channel, `None` leaves the velocities
+	/// unchanged.
+	pub fn set_curve(&mut self, channel: u4, curve: Option<Curve>) {
+		self.curves[channel.as_int() as usize] = curve;
+	}
+}
+
+impl<C: Connection> Connection for Velocity<C> {
+	fn play(&mut self, mut event: MidiEvent) -> bool {
+		if let MidiMessage::NoteOn { vel, .. } = &mut event.message {
+			// A velocity of 0 means NoteOff.
+			if *vel > 0 {
+				if let Some(curve) = self.curve(event.channel) {
+					*vel = curve.apply(*vel);
+				}
+			}
+		}
+		self.con.play(event)
+	}
+
+	fn send_sys_rt(&mut self, msg: SystemRealtime) {
+		self.con.send_sys_rt(msg);
+	}
+
+	fn send_sys_common(&mut self, msg: SystemCommon<'_>) {
+		self.con.send_sys_common(msg);
+	}
+
+	fn send_sys_ex(&mut self, data: &[u8]) {
+		self.con.send_sys_ex(data);
+	}
+
+	fn send_escape(&mut self, data: &[u8]) {
+		self.con.send_escape(data);
+	}
+
+	fn send_text(&mut self, kind: TextKind, text: &[u8]) {
+		self.con.send_text(kind, text);
+	}
+
+	fn all_notes_off(&mut self) {
+		self.con.all_notes_off();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_support::{on, Capture};
+
+	#[test]
+	fn curves() {
+		let apply = |curve: Curve, vel: u8| curve.apply(vel.into()).as_int();
+
+		assert_eq!(apply(Curve::Scale(0.5), 100), 50);
+		assert_eq!(apply(Curve::Scale(2.0), 100), 127);
+		assert_eq!(apply(Curve::Scale(0.0), 100), 1);
+		assert_eq!(apply(Curve::Exponential(1.0), 64), 64);
+		assert_eq!(apply(Curve::Exponential(0.5), 32), 64);
+		assert_eq!(apply(Curve::Exponential(2.0), 64), 32);
+		let mut table = Box::new([0; 128]);
+		table[10] = 90;
+		assert_eq!(apply(Curve::Table(table), 10), 90);
+		assert_eq!(apply(Curve::Fixed(0), 10), 1);
+		assert_eq!(apply(Curve::Clamp { min: 20, max: 90 }, 10), 20);
+		assert_eq!(apply(Curve::Clamp { min: 20, max: 90 }, 100), 90);
+
+		let on = |ch: u8, vel: u8| on(ch, 60, vel);
+
+		let mut con = Velocity::new(Capture::default(), Curve::Fixed(100));
+		con.set_curve(1.into(), None);
+		con.play(on(0, 50));
+		con.play(on(0, 0));
+		con.play(on(1, 50));
+		assert_eq!(con.con.0, [on(0, 100), on(0, 0), on(1, 50)]);
+	}
+}
@@ -0,0 +1,57 @@
+use std::{fmt, io};
+
+use crate::timers::TimeFormatError;
+
+/// The error type used throughout this crate.
+#[derive(Debug)]
+pub enum Error {
+	/// An I/O error, for example while reading a MIDI file.
+	Io(io::Error),
+	/// A MIDI file could not be parsed.
+	Parse(midly::Error),
+	/// The [Timing](midly::Timing) of a file is not supported by the requested
+	/// [Timer](crate::Timer), see [TimeFormatError].
+	UnsupportedTiming,
+	/// A [TrackEventKind](midly::TrackEventKind) has no corresponding
+	/// [Event](crate::Event).
+	UnsupportedEvent,
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Io(e) => write!(f, "i/o error: {}", e),
+			Self::Parse(e) => write!(f, "failed to parse the MIDI file: {}", e),
+			Self::UnsupportedTiming => f.write_str("unsupported time format"),
+			Self::UnsupportedEvent => f.write_str("not a valid event"),
+		}
+	}
+}
+
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Io(e) => Some(e),
+			Self::Parse(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for Error {
+	fn from(e: io::Error) -> Self {
+		Self::Io(e)
+	}
+}
+
+impl From<midly::Error> for Error {
+	fn from(e: midly::Error) -> Self {
+		Self::Parse(e)
+	}
+}
+
+impl From<TimeFormatError> for Error {
+	fn from(_: TimeFormatError) -> Self {
+		Self::UnsupportedTiming
+	}
+}
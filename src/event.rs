@@ -8,6 +8,8 @@ use std::{
 
 use midly::{live::LiveEvent, num::u4, MetaMessage, MidiMessage, TrackEventKind};
 
+use crate::Error;
+
 /// Represents a single moment (tick) in a MIDI track.
 #[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
 pub struct Moment {
@@ -15,6 +17,12 @@ pub struct Moment {
 	pub events: Vec<Event>,
 }
 
+impl From<Vec<Event>> for Moment {
+	fn from(events: Vec<Event>) -> Self {
+		Self { events }
+	}
+}
+
 impl Deref for Moment {
 	type Target = Vec<Event>;
 	fn deref(&self) -> &Self::Target {
@@ -29,7 +37,11 @@ impl DerefMut for Moment {
 }
 
 /// Represents a single MIDI event.
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+///
+/// # Notes
+/// Since it can hold SysEx messages and text, [Event] does not implement
+/// [Copy], unlike in nodi 1.0.
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
 pub enum Event {
 	/// Represents a tempo change message.
 	/// The wrapped `u32` represents microseconds per beat.
@@ -43,6 +55,96 @@ pub enum Event {
 	KeySignature(i8, bool),
 	/// Represents a MIDI event.
 	Midi(MidiEvent),
+	/// A system exclusive message, holding the raw bytes to be sent to the
+	/// device.
+	///
+	/// The bytes include the leading `0xF0` byte and usually end with `0xF7`.
+	SysEx(Vec<u8>),
+	/// An escape sequence, such as the continuation packet of a split SysEx
+	/// message, holding the raw bytes to be sent to the device as they appear
+	/// in the file.
+	Escape(Vec<u8>),
+	/// A text meta event, such as a lyric or a marker.
+	///
+	/// The text is kept as raw bytes since MIDI files do not specify an
+	/// encoding; it is usually ASCII, Latin-1 or UTF-8.
+	Text(TextKind, Vec<u8>),
+}
+
+/// A time signature, as carried by [Event::TimeSignature].
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub struct TimeSignature {
+	/// Beats per bar.
+	pub numerator: u8,
+	/// The note value of a beat, as a negative power of 2; `2` means a
+	/// quarter note, `3` an eighth note and so on.
+	pub denominator: u8,
+	/// MIDI clocks per metronome click.
+	pub clocks_per_click: u8,
+	/// Notated 32nd notes per MIDI quarter note (24 MIDI clocks).
+	pub notes_per_quarter: u8,
+}
+
+impl Default for TimeSignature {
+	/// Returns a 4/4 time signature, which is the default as per the MIDI
+	/// specification.
+	fn default() -> Self {
+		Self {
+			numerator: 4,
+			denominator: 2,
+			clocks_per_click: 24,
+			notes_per_quarter: 8,
+		}
+	}
+}
+
+impl TimeSignature {
+	/// Returns the length of a beat, in ticks.
+	///
+	/// # Arguments
+	/// - `ticks_per_beat`: Ticks per quarter note, as found in
+	///   [Timing::Metrical](midly::Timing::Metrical).
+	pub fn beat_ticks(&self, ticks_per_beat: u16) -> f64 {
+		ticks_per_beat as f64 * 4.0 / 2_f64.powi(self.denominator as i32)
+	}
+
+	/// Returns the length of a bar, in ticks.
+	///
+	/// # Arguments
+	/// - `ticks_per_beat`: Ticks per quarter note, as found in
+	///   [Timing::Metrical](midly::Timing::Metrical).
+	pub fn bar_ticks(&self, ticks_per_beat: u16) -> u32 {
+		(self.beat_ticks(ticks_per_beat) * self.numerator as f64) as u32
+	}
+}
+
+/// A key signature, as carried by [Event::KeySignature].
+///
+/// The default value is C major.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash, Default)]
+pub struct KeySignature {
+	/// Negative numbers indicate number of flats and positive numbers indicate
+	/// number of sharps.
+	pub sharps: i8,
+	/// `true` for a minor scale, `false` for a major scale.
+	pub minor: bool,
+}
+
+/// The kind of an [Event::Text].
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
+pub enum TextKind {
+	/// Arbitrary text.
+	Text,
+	/// A lyric, usually a syllable, to be sung at this moment.
+	Lyric,
+	/// A marker, such as the name of a section ("Verse", "Chorus"...).
+	Marker,
+	/// A cue point, describing something happening on stage or screen.
+	CuePoint,
+	/// The name of the track (or of the sequence, in the first track).
+	TrackName,
+	/// The name of the instrument used in the track.
+	InstrumentName,
 }
 
 /// Represents a MIDI message.
@@ -77,13 +179,68 @@ impl MidiEvent {
 	}
 }
 
+impl Event {
+	/// Converts `self` back into a [TrackEventKind].
+	///
+	/// This is the inverse of [Event::try_from].
+	pub fn as_track_event(&self) -> TrackEventKind<'_> {
+		match *self {
+			Self::Midi(MidiEvent { channel, message }) => TrackEventKind::Midi { channel, message },
+			Self::Tempo(n) => TrackEventKind::Meta(MetaMessage::Tempo(n.into())),
+			Self::TimeSignature(a, b, c, d) => {
+				TrackEventKind::Meta(MetaMessage::TimeSignature(a, b, c, d))
+			}
+			Self::KeySignature(a, b) => TrackEventKind::Meta(MetaMessage::KeySignature(a, b)),
+			Self::SysEx(ref data) => match data.split_first() {
+				Some((0xF0, rest)) => TrackEventKind::SysEx(rest),
+				_ => TrackEventKind::Escape(data),
+			},
+			Self::Escape(ref data) => TrackEventKind::Escape(data),
+			Self::Text(kind, ref text) => TrackEventKind::Meta(match kind {
+				TextKind::Text => MetaMessage::Text(text),
+				TextKind::Lyric => MetaMessage::Lyric(text),
+				TextKind::Marker => MetaMessage::Marker(text),
+				TextKind::CuePoint => MetaMessage::CuePoint(text),
+				TextKind::TrackName => MetaMessage::TrackName(text),
+				TextKind::InstrumentName => MetaMessage::InstrumentName(text),
+			}),
+		}
+	}
+
+	/// Returns the [TimeSignature] carried by `self`, if it is an
+	/// [Event::TimeSignature].
+	pub fn as_time_signature(&self) -> Option<TimeSignature> {
+		match *self {
+			Self::TimeSignature(numerator, denominator, clocks_per_click, notes_per_quarter) => {
+				Some(TimeSignature {
+					numerator,
+					denominator,
+					clocks_per_click,
+					notes_per_quarter,
+				})
+			}
+			_ => None,
+		}
+	}
+
+	/// Returns the [KeySignature] carried by `self`, if it is an
+	/// [Event::KeySignature].
+	pub fn as_key_signature(&self) -> Option<KeySignature> {
+		match *self {
+			Self::KeySignature(sharps, minor) => Some(KeySignature { sharps, minor }),
+			_ => None,
+		}
+	}
+}
+
 impl TryFrom<TrackEventKind<'_>> for Event {
-	type Error = &'static str;
+	type Error = Error;
 
 	/// Tries to create [Self] from a [TrackEventKind].
 	///
 	/// # Errors
-	/// Will return an error if the given [TrackEventKind] is not compatible.
+	/// Will return [Error::UnsupportedEvent] if the given [TrackEventKind] is
+	/// not compatible.
 	fn try_from(event: TrackEventKind<'_>) -> Result<Self, Self::Error> {
 		Ok(match event {
 			TrackEventKind::Midi { channel, message } => Self::Midi(MidiEvent { channel, message }),
@@ -92,7 +249,28 @@ impl TryFrom<TrackEventKind<'_>> for Event {
 				Self::TimeSignature(a, b, c, d)
 			}
 			TrackEventKind::Meta(MetaMessage::KeySignature(a, b)) => Self::KeySignature(a, b),
-			_ => return Err("not a valid event"),
+			TrackEventKind::SysEx(data) => {
+				let mut buf = Vec::with_capacity(data.len() + 1);
+				buf.push(0xF0);
+				buf.extend_from_slice(data);
+				Self::SysEx(buf)
+			}
+			TrackEventKind::Escape(data) => Self::Escape(data.to_vec()),
+			TrackEventKind::Meta(MetaMessage::Text(s)) => Self::Text(TextKind::Text, s.to_vec()),
+			TrackEventKind::Meta(MetaMessage::Lyric(s)) => Self::Text(TextKind::Lyric, s.to_vec()),
+			TrackEventKind::Meta(MetaMessage::Marker(s)) => {
+				Self::Text(TextKind::Marker, s.to_vec())
+			}
+			TrackEventKind::Meta(MetaMessage::CuePoint(s)) => {
+				Self::Text(TextKind::CuePoint, s.to_vec())
+			}
+			TrackEventKind::Meta(MetaMessage::TrackName(s)) => {
+				Self::Text(TextKind::TrackName, s.to_vec())
+			}
+			TrackEventKind::Meta(MetaMessage::InstrumentName(s)) => {
+				Self::Text(TextKind::InstrumentName, s.to_vec())
+			}
+			_ => return Err(Error::UnsupportedEvent),
 		})
 	}
 }
@@ -33,6 +33,22 @@ impl Moment {
 	}
 }
 
+impl Event {
+	// Transposes `self` if it is a MIDI event, returns false if the note falls out of range.
+	pub(crate) fn transpose(&mut self, shift: i8, transpose_ch9: bool) -> bool {
+		match self {
+			Self::Midi(e) => match e.transposed(shift, transpose_ch9) {
+				Some(transposed) => {
+					*e = transposed;
+					true
+				}
+				None => false,
+			},
+			_ => true,
+		}
+	}
+}
+
 impl MidiEvent {
 	/// Transposes `self` `shift` amount of half-steps.
 	/// Only MIDI events with keys are transposed, others are left as is.
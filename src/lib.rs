@@ -2,14 +2,22 @@
 #![warn(missing_docs, rustdoc::missing_crate_level_docs)]
 #![doc = include_str!("doc_lib.md")]
 
+mod chase;
+pub mod compose;
+mod error;
 mod event;
 mod player;
 mod sheet;
+mod tempo_map;
+#[cfg(test)]
+mod test_support;
 pub mod timers;
 
 use std::time::Duration;
 
-pub use self::{event::*, player::*, sheet::*};
+pub use self::{
+	chase::ChaseState, error::Error, event::*, player::*, sheet::*, tempo_map::TempoMap,
+};
 #[cfg(feature = "midir")]
 pub use midir;
 pub use midly;
@@ -30,6 +38,63 @@ pub trait Timer {
 	/// - `tempo`: Represents microseconds per a beat (MIDI quarter note).
 	fn change_tempo(&mut self, tempo: u32);
 
+	/// Returns the current tempo, in microseconds per beat, if the timer has
+	/// one.
+	///
+	/// This is used to restore the tempo, for example between the passes of
+	/// [Player::play_loop].
+	///
+	/// The default implementation returns `None`.
+	fn tempo(&self) -> Option<u32> {
+		None
+	}
+
+	/// Forgets about the previous sleeps.
+	///
+	/// This is called when the playback is interrupted, for example when it's
+	/// paused. Timers that make up for the time spent between two sleeps should
+	/// start over.
+	///
+	/// The default implementation of this method does nothing.
+	fn reset(&mut self) {}
+
+	/// Returns the duration of `n_ticks` at the current tempo, without making
+	/// up for the time spent since the previous sleep.
+	///
+	/// This is used to measure how late the playback is, see
+	/// [PlaybackReport].
+	///
+	/// The default implementation calls [Timer::sleep_duration]; timers that
+	/// adjust the duration there should override it.
+	fn nominal_duration(&mut self, n_ticks: u32) -> Duration {
+		self.sleep_duration(n_ticks)
+	}
+
+	/// Returns the position, in ticks, the playback should jump to, if any.
+	///
+	/// Timers that follow another device, such as an
+	/// [ExternalClock](timers::ExternalClock), return the new position of the
+	/// device once it is relocated. [Player::play] and its variants check it
+	/// after every sleep and jump there, chasing the state of the track as
+	/// [Player::play_from] does.
+	///
+	/// The default implementation returns `None`.
+	fn take_seek(&mut self) -> Option<u32> {
+		None
+	}
+
+	/// Returns `true` if the next [sleep](Timer::sleep) is going to pause
+	/// the playback.
+	///
+	/// [Player::play] and its variants check it before every sleep and
+	/// release the sounding notes, so that they don't ring for the whole
+	/// pause.
+	///
+	/// The default implementation returns `false`.
+	fn will_pause(&mut self) -> bool {
+		false
+	}
+
 	/// Sleeps given number of ticks.
 	/// The provided implementation will sleep the thread  for
 	/// `self.sleep_duration(n_ticks)`.
@@ -61,4 +126,29 @@ pub trait Timer {
 		}
 		counter
 	}
+
+	/// Calculates the length of a [SparseSheet].
+	///
+	/// This returns the same value [Timer::duration] would return for the
+	/// equivalent [Sheet].
+	///
+	/// # Notes
+	/// The default implementation modifies `self` if a tempo event is found.
+	/// It uses [Timer::nominal_duration], so it's suitable for every timer
+	/// that implements it correctly.
+	fn sparse_duration(&mut self, sheet: &SparseSheet) -> Duration {
+		let mut counter = Duration::default();
+		// Number of ticks accounted for so far.
+		let mut pos = 0;
+		for (tick, moment) in sheet.iter() {
+			counter += self.nominal_duration(tick + 1 - pos);
+			pos = tick + 1;
+			for event in &moment.events {
+				if let Event::Tempo(val) = event {
+					self.change_tempo(*val);
+				}
+			}
+		}
+		counter + self.nominal_duration(sheet.len() - pos)
+	}
 }
@@ -1,27 +1,64 @@
+mod clock;
+mod future;
+mod handle;
+mod notes;
+mod playlist;
+mod record;
+mod repeat;
+mod report;
+mod writer;
+
 #[cfg(feature = "midir")]
 use midir::{self, MidiOutputConnection};
+use std::{
+	iter::Peekable,
+	time::{Duration, Instant},
+};
+
 use midly::{
 	live::{SystemCommon, SystemRealtime},
-	MidiMessage,
+	MidiMessage, Timing,
 };
 
 use crate::{
-	event::{Event, MidiEvent, Moment},
-	Timer,
+	event::{Event, KeySignature, MidiEvent, Moment, TextKind, TimeSignature},
+	ChaseState, TempoMap, Timeline, TimelineIter, Timer,
 };
 
+pub use future::{AsyncAdapter, AsyncConnection, Sleep};
+pub use handle::PlaybackHandle;
+pub use notes::ActiveNotes;
+pub use playlist::Playlist;
+pub use record::Recorder;
+pub use repeat::Repeat;
+pub use report::{PlaybackReport, StopReason};
+pub use writer::MidiWriter;
+
 #[doc = include_str!("doc_player.md")]
 pub struct Player<T: Timer, C: Connection> {
 	/// An active midi connection.
 	pub con: C,
 	timer: T,
+	observer: Option<Box<dyn Observer + Send>>,
+	notes: ActiveNotes,
+	clock: Option<clock::Clock>,
+	stats: report::Stats,
+	report: Option<PlaybackReport>,
 }
 
 impl<T: Timer, C: Connection> Player<T, C> {
 	/// Creates a new [Player] with the given [Timer] and
 	/// [Connection].
 	pub fn new(timer: T, con: C) -> Self {
-		Self { con, timer }
+		Self {
+			con,
+			timer,
+			observer: None,
+			notes: ActiveNotes::new(),
+			clock: None,
+			stats: report::Stats::new(0),
+			report: None,
+		}
 	}
 
 	/// Changes `self.timer`, returning the old one.
@@ -29,40 +66,284 @@ impl<T: Timer, C: Connection> Player<T, C> {
 		std::mem::replace(&mut self.timer, timer)
 	}
 
-	/// Plays the given [Moment] slice.
+	/// Sets the [Observer] notified of the playback progress, returning the
+	/// old one.
+	pub fn set_observer<O: Observer + Send + 'static>(
+		&mut self,
+		observer: O,
+	) -> Option<Box<dyn Observer + Send>> {
+		self.observer.replace(Box::new(observer))
+	}
+
+	/// Removes the [Observer] and returns it.
+	pub fn take_observer(&mut self) -> Option<Box<dyn Observer + Send>> {
+		self.observer.take()
+	}
+
+	/// Returns the notes that are currently sounding.
+	pub fn active_notes(&self) -> &ActiveNotes {
+		&self.notes
+	}
+
+	/// Turns off every sounding note, see [ActiveNotes::release].
+	///
+	/// This is done automatically when the playback stops for any reason.
+	pub fn release_notes(&mut self) {
+		for e in self.notes.release() {
+			// Sent even if the connection asked to stop, so that no note is left stuck.
+			self.con.play(e);
+		}
+	}
+
+	/// Plays the given [Sheet](crate::Sheet), [SparseSheet](crate::SparseSheet)
+	/// or [Moment](crate::Moment) slice.
 	///
 	/// # Notes
 	/// The tempo change events are handled by `self.timer` and playing sound by
 	/// `self.con`.
 	///
-	/// Stops playing if [Connection::play] returns `false`.
+	/// Stops playing if [Connection::play] returns `false`. Either way, the
+	/// notes that are still sounding are turned off at the end, see
+	/// [Player::release_notes].
+	///
+	/// Returns `true` if the track is played through the end, `false` otherwise.
+	/// Use [Player::play_report] to know more.
+	pub fn play<'a, S: Into<Timeline<'a>>>(&mut self, sheet: S) -> bool {
+		self.play_report(sheet).is_finished()
+	}
+
+	/// Plays the given track like [Player::play], returning a
+	/// [PlaybackReport].
+	pub fn play_report<'a, S: Into<Timeline<'a>>>(&mut self, sheet: S) -> PlaybackReport {
+		let timeline = sheet.into();
+		self.started(0);
+		let done = self.play_moments(timeline, timeline.iter().peekable(), 0);
+		self.stop_report(done)
+	}
+
+	/// Plays the given track starting from `tick`.
+	///
+	/// Unlike slicing a [Sheet](crate::Sheet) with `&sheet[tick..]`, the state
+	/// of the track before `tick` is chased: the last tempo is applied and the
+	/// programs, controllers, pitch bends and SysEx messages that came before
+	/// are sent first, see [ChaseState].
+	///
 	/// Returns `true` if the track is played through the end, `false` otherwise.
-	pub fn play(&mut self, sheet: &[Moment]) -> bool {
-		let mut counter = 0_u32;
-
-		for moment in sheet {
-			if !moment.is_empty() {
-				self.timer.sleep(counter);
-				counter = 0;
-
-				for event in &moment.events {
-					match event {
-						Event::Tempo(val) => self.timer.change_tempo(*val),
-						Event::Midi(msg) => {
-							if !self.con.play(*msg) {
-								return false;
-							}
-						}
-						_ => (),
-					};
+	/// Use [Player::play_from_report] to know more.
+	pub fn play_from<'a, S: Into<Timeline<'a>>>(&mut self, sheet: S, tick: u32) -> bool {
+		self.play_from_report(sheet, tick).is_finished()
+	}
+
+	/// Plays the given track starting from `tick` like [Player::play_from],
+	/// returning a [PlaybackReport].
+	pub fn play_from_report<'a, S: Into<Timeline<'a>>>(
+		&mut self,
+		sheet: S,
+		tick: u32,
+	) -> PlaybackReport {
+		let timeline = sheet.into();
+		let mut moments = timeline.iter().peekable();
+		let state = chase(&mut moments, tick);
+
+		self.started(tick);
+		let done = state.events().iter().all(|e| self.handle(e))
+			&& self.play_moments(timeline, moments, tick);
+		self.stop_report(done)
+	}
+
+	/// Plays the given track starting from the given time, chasing the state
+	/// before it.
+	///
+	/// # Arguments
+	/// - `timing`: The timing of the track, obtained from a
+	///   [Header](midly::Header). It is used to convert `time` to ticks with a
+	///   [TempoMap].
+	///
+	/// # Notes
+	/// The tempo of the timer is set to [TempoMap::DEFAULT_TEMPO] first, the
+	/// tempo the [TempoMap] assumes until the first tempo change, so that the
+	/// playback agrees with it on tracks that don't set a tempo at the start.
+	///
+	/// See [Player::play_from] for more.
+	pub fn play_from_time<'a, S: Into<Timeline<'a>>>(
+		&mut self,
+		sheet: S,
+		timing: Timing,
+		time: Duration,
+	) -> bool {
+		let timeline = sheet.into();
+		let tick = TempoMap::new(timeline, timing).duration_to_tick(time);
+		self.timer.change_tempo(TempoMap::DEFAULT_TEMPO);
+		self.play_from(timeline, tick)
+	}
+
+	// Plays the remaining `moments` of `timeline`, starting from the tick `start`.
+	// Jumps to the position requested by the timer, see Timer::take_seek.
+	fn play_moments<'a>(
+		&mut self,
+		timeline: Timeline<'a>,
+		mut moments: Peekable<TimelineIter<'a>>,
+		start: u32,
+	) -> bool {
+		let mut last_tick = start;
+
+		while let Some((tick, moment)) = moments.next() {
+			self.sleep_until(last_tick, tick);
+			if let Some(to) = self.timer.take_seek() {
+				moments = timeline.iter().peekable();
+				if !self.seek(&mut moments, to) {
+					return false;
 				}
+				last_tick = to;
+				continue;
 			}
+			last_tick = tick;
 
-			counter += 1;
+			if !self.play_moment(tick, moment) {
+				return false;
+			}
 		}
 
 		true
 	}
+
+	// Jumps to `tick`, playing the state the `moments` before it leave behind.
+	// Returns false if playback should stop.
+	fn seek(&mut self, moments: &mut Peekable<TimelineIter<'_>>, tick: u32) -> bool {
+		self.release_notes();
+		self.reschedule(tick, Instant::now());
+		self.locate(tick);
+		let state = chase(moments, tick);
+		state.events().iter().all(|e| self.handle(e))
+	}
+
+	// Plays a moment at the given position, returns false if playback should stop.
+	fn play_moment(&mut self, tick: u32, moment: &Moment) -> bool {
+		self.record_moment(tick);
+		self.notify(|o| o.on_moment(tick, moment));
+		moment.events.iter().all(|e| self.handle(e))
+	}
+
+	// Plays or applies a single event, returns false if playback should stop.
+	fn handle(&mut self, event: &Event) -> bool {
+		match event {
+			Event::Tempo(val) => {
+				self.timer.change_tempo(*val);
+				self.notify(|o| o.on_tempo(*val));
+			}
+			Event::Midi(msg) => {
+				self.notes.update(msg);
+				let accepted = self.con.play(*msg);
+				self.stats.sent(accepted);
+				return accepted;
+			}
+			Event::SysEx(data) => {
+				self.con.send_sys_ex(data);
+				self.stats.sent(true);
+			}
+			Event::Escape(data) => {
+				self.con.send_escape(data);
+				self.stats.sent(true);
+			}
+			Event::Text(kind, text) => {
+				self.con.send_text(*kind, text);
+				self.stats.sent(true);
+			}
+			Event::TimeSignature(..) => {
+				let ts = event.as_time_signature().unwrap();
+				self.notify(|o| o.on_time_signature(ts));
+			}
+			Event::KeySignature(..) => {
+				let ks = event.as_key_signature().unwrap();
+				self.notify(|o| o.on_key_signature(ks));
+			}
+		}
+		true
+	}
+
+	fn notify<F: FnOnce(&mut (dyn Observer + Send))>(&mut self, f: F) {
+		if let Some(o) = &mut self.observer {
+			f(o.as_mut());
+		}
+	}
+
+	// Starts the clock and notifies the observer that the playback starts at `tick`.
+	fn started(&mut self, tick: u32) {
+		self.stats = report::Stats::new(tick);
+		self.start_clock(tick);
+		self.notify(|o| o.on_start(tick));
+	}
+
+	// Releases the sounding notes, stops the clock and notifies the observer that the playback is over, returns `done`.
+	fn stopped(&mut self, done: bool) -> bool {
+		self.stop_report(done);
+		done
+	}
+
+	// The same as stopped, but returns the report of the playback.
+	fn stop_report(&mut self, done: bool) -> PlaybackReport {
+		self.release_notes();
+		self.transport(SystemRealtime::Stop);
+		self.notify(|o| o.on_stop(done));
+		self.finish(done)
+	}
+}
+
+// Consumes the moments before `tick`, returning the state they leave behind.
+fn chase(moments: &mut Peekable<TimelineIter<'_>>, tick: u32) -> ChaseState {
+	let mut state = ChaseState::new();
+	while let Some((_, moment)) = moments.next_if(|(t, _)| *t < tick) {
+		for e in &moment.events {
+			state.update(e);
+		}
+	}
+	state
+}
+
+/// Receives notifications about the progress of a [Player].
+///
+/// Set one with [Player::set_observer]; every method has a default
+/// implementation that does nothing, so implement only the ones you need.
+///
+/// The methods are called from the thread doing the playback and between
+/// sleeps, so they should return quickly.
+///
+/// # Examples
+/// ```
+/// use nodi::{Moment, Observer};
+///
+/// struct Progress {
+///     len: u32,
+/// }
+///
+/// impl Observer for Progress {
+///     fn on_moment(&mut self, tick: u32, _: &Moment) {
+///         println!("{:.1}%", tick as f64 * 100.0 / self.len as f64);
+///     }
+/// }
+/// ```
+pub trait Observer {
+	/// Called when the playback starts, with the position it starts from.
+	fn on_start(&mut self, _tick: u32) {}
+
+	/// Called when the playback is over.
+	///
+	/// `done` is `true` if the track was played through the end.
+	fn on_stop(&mut self, _done: bool) {}
+
+	/// Called when a non-empty [Moment] is reached, before its events are
+	/// played.
+	fn on_moment(&mut self, _tick: u32, _moment: &Moment) {}
+
+	/// Called when the tempo changes, with the new tempo in microseconds per
+	/// beat.
+	fn on_tempo(&mut self, _tempo: u32) {}
+
+	/// Called when a time signature event is reached.
+	fn on_time_signature(&mut self, _time_sig: TimeSignature) {}
+
+	/// Called when a key signature event is reached.
+	fn on_key_signature(&mut self, _key_sig: KeySignature) {}
 }
 
 /// Any type that can play sound, given a [MidiEvent].
@@ -85,6 +366,33 @@ pub trait Connection {
 	/// The default implementation of this method does nothing.
 	fn send_sys_common(&mut self, _msg: SystemCommon<'_>) {}
 
+	/// Sends a system exclusive message.
+	///
+	/// `data` holds the raw bytes to be sent, as stored in [Event::SysEx].
+	///
+	/// The default implementation of this method does nothing.
+	fn send_sys_ex(&mut self, _data: &[u8]) {}
+
+	/// Sends an escape sequence: arbitrary bytes, such as realtime messages
+	/// or a SysEx message split into packets.
+	///
+	/// `data` holds the raw bytes to be sent, as stored in [Event::Escape].
+	///
+	/// The default implementation of this method calls
+	/// [Connection::send_sys_ex], since both send their bytes as they are.
+	fn send_escape(&mut self, data: &[u8]) {
+		self.send_sys_ex(data);
+	}
+
+	/// Receives a text event (lyrics, markers...) as it is reached during
+	/// playback.
+	///
+	/// Text events are not sent to MIDI devices; implement this if you want to
+	/// display them, for example for karaoke.
+	///
+	/// The default implementation of this method does nothing.
+	fn send_text(&mut self, _kind: TextKind, _text: &[u8]) {}
+
 	/// Turns all notes off.
 	///
 	/// The provided implementation simply blasts every channel with NoteOff messages for every possible note; `16 * 128 = 2048` messages will be sent.
@@ -124,4 +432,43 @@ impl Connection for MidiOutputConnection {
 		let _ = midly::live::LiveEvent::Common(msg).write(&mut buf);
 		let _ = self.send(&buf);
 	}
+
+	fn send_sys_ex(&mut self, data: &[u8]) {
+		let _ = self.send(data);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		test_support::on,
+		timers::{Ticker, VirtualClock},
+		Sheet,
+	};
+
+	#[test]
+	fn play_from_time() {
+		let mut sheet = Sheet::new();
+		sheet.extend((0..1500).map(|_| Default::default()));
+		sheet[960].push(Event::Midi(on(0, 60, 100)));
+		sheet[1440].push(Event::Midi(on(0, 62, 100)));
+
+		// No tempo is set: a second is 960 ticks at the default tempo, which
+		// the timer follows too.
+		let clock = VirtualClock::new(Ticker::new(480));
+		let time = clock.time();
+		let mut player = Player::new(clock, Recorder::new(time));
+		assert!(player.play_from_time(
+			&sheet,
+			Timing::Metrical(480.into()),
+			Duration::from_secs(1)
+		));
+		let events = player.con.events();
+		assert_eq!(events[0], (Duration::ZERO, Event::Midi(on(0, 60, 100))));
+		assert_eq!(
+			events[1],
+			(Duration::from_millis(500), Event::Midi(on(0, 62, 100)))
+		);
+	}
 }
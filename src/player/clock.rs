@@ -0,0 +1,237 @@
+use midly::{
+	live::{SystemCommon, SystemRealtime},
+	num::u14,
+};
+
+use super::{Connection, Player};
+use crate::Timer;
+
+// The state of the MIDI beat clock sent by a Player.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) struct Clock {
+	ticks_per_beat: u16,
+	// Index of the next pulse, counting from the start of the track.
+	next: u32,
+	// Whether the last transport message sent started the other devices.
+	running: bool,
+}
+
+impl Clock {
+	// Returns the position of a pulse in ticks; there are 24 pulses per beat.
+	fn pulse_tick(&self, pulse: u32) -> u32 {
+		(pulse as u64 * self.ticks_per_beat as u64).div_ceil(24) as u32
+	}
+}
+
+impl<T: Timer, C: Connection> Player<T, C> {
+	/// Enables or disables sending MIDI beat clock and transport messages.
+	///
+	/// When enabled, the [Player] sends the following through
+	/// [Connection::send_sys_rt] and [Connection::send_sys_common], so that
+	/// other devices (drum machines, sequencers, DAWs...) can follow the
+	/// playback:
+	/// - [SystemRealtime::Start] when playing from the beginning of a track.
+	/// - [SystemCommon::SongPosition] followed by [SystemRealtime::Continue]
+	///   when playing from the middle of a track. Song positions are counted in
+	///   sixteenth notes, so the position of the next sixteenth note is sent.
+	/// - [SystemRealtime::TimingClock] 24 times per beat (quarter note),
+	///   following the tempo changes.
+	/// - [SystemRealtime::Stop], [SystemCommon::SongPosition] and
+	///   [SystemRealtime::Continue] when the playback jumps elsewhere, such
+	///   as when seeking or looping; many devices ignore song positions while
+	///   running.
+	/// - [SystemRealtime::Stop] when the playback stops.
+	///
+	/// # Arguments
+	/// - `ticks_per_beat`: The ticks per beat of the track, obtained from a
+	///   [Header](midly::Header), or `None` to disable the clock. A value of
+	///   `0` also disables it.
+	pub fn set_clock(&mut self, ticks_per_beat: Option<u16>) {
+		self.clock = ticks_per_beat
+			.filter(|&n| n > 0)
+			.map(|ticks_per_beat| Clock {
+				ticks_per_beat,
+				next: 0,
+				running: false,
+			});
+	}
+
+	// Sends a transport message, if the clock is enabled.
+	pub(super) fn transport(&mut self, msg: SystemRealtime) {
+		if let Some(clock) = &mut self.clock {
+			clock.running = !matches!(msg, SystemRealtime::Stop);
+			self.con.send_sys_rt(msg);
+		}
+	}
+
+	// Sends the transport messages for starting the playback at `tick`.
+	pub(super) fn start_clock(&mut self, tick: u32) {
+		if tick == 0 {
+			if let Some(clock) = &mut self.clock {
+				clock.next = 0;
+			}
+			self.transport(SystemRealtime::Start);
+		} else {
+			self.locate(tick);
+			self.transport(SystemRealtime::Continue);
+		}
+	}
+
+	// Sends the song position of `tick`, if the clock is enabled, stopping the
+	// other devices around it if they are running.
+	pub(super) fn locate(&mut self, tick: u32) {
+		if let Some(clock) = &mut self.clock {
+			// Rounded up to the next sixteenth note, the pulses resume from there.
+			let tpb = clock.ticks_per_beat as u64;
+			let pos = (tick as u64 * 4)
+				.div_ceil(tpb)
+				.min(u14::max_value().as_int() as u64);
+			clock.next = pos as u32 * 6;
+			let running = clock.running;
+			if running {
+				self.con.send_sys_rt(SystemRealtime::Stop);
+			}
+			self.con
+				.send_sys_common(SystemCommon::SongPosition(u14::new(pos as u16)));
+			if running {
+				self.con.send_sys_rt(SystemRealtime::Continue);
+			}
+		}
+	}
+
+	// Returns the position of the next clock pulse, if the clock is enabled.
+	pub(super) fn next_pulse(&self) -> Option<u32> {
+		self.clock.map(|c| c.pulse_tick(c.next))
+	}
+
+	// Sends the next clock pulse.
+	pub(super) fn pulse(&mut self) {
+		if let Some(clock) = &mut self.clock {
+			clock.next += 1;
+			self.con.send_sys_rt(SystemRealtime::TimingClock);
+		}
+	}
+
+	// Sleeps from the tick `from` until the tick `to`, sending the clock pulses in between.
+	pub(super) fn sleep_until(&mut self, from: u32, to: u32) {
+		let mut tick = from;
+		while let Some(pulse) = self.next_pulse().filter(|p| *p < to) {
+			if pulse > tick {
+				self.sleep_ticks(pulse - tick);
+				tick = pulse;
+			}
+			self.pulse();
+		}
+		self.sleep_ticks(to - tick);
+	}
+
+	// Sleeps `n_ticks`, releasing the sounding notes first if the timer pauses.
+	fn sleep_ticks(&mut self, n_ticks: u32) {
+		if self.timer.will_pause() {
+			self.release_notes();
+		}
+		self.timer.sleep(n_ticks);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{timers::FixedTempo, Event, MidiEvent, Repeat, Sheet};
+
+	#[derive(Debug, PartialEq, Eq)]
+	enum Msg {
+		Rt(SystemRealtime),
+		Position(u16),
+		Midi,
+	}
+
+	struct Transport(Vec<Msg>);
+
+	impl Connection for Transport {
+		fn play(&mut self, _: MidiEvent) -> bool {
+			self.0.push(Msg::Midi);
+			true
+		}
+
+		fn send_sys_rt(&mut self, msg: SystemRealtime) {
+			self.0.push(Msg::Rt(msg));
+		}
+
+		fn send_sys_common(&mut self, msg: SystemCommon<'_>) {
+			if let SystemCommon::SongPosition(pos) = msg {
+				self.0.push(Msg::Position(pos.as_int()));
+			}
+		}
+	}
+
+	#[test]
+	fn clock() {
+		let note = Event::Midi(MidiEvent {
+			channel: 0.into(),
+			message: midly::MidiMessage::NoteOn {
+				key: 60.into(),
+				vel: 100.into(),
+			},
+		});
+		let mut sheet = Sheet::new();
+		sheet.extend((0..20).map(|_| Default::default()));
+		sheet[5].push(note.clone());
+		sheet[12].push(note);
+
+		// 48 ticks per beat, a pulse every other tick.
+		let mut player = Player::new(FixedTempo(0), Transport(Vec::new()));
+		player.set_clock(Some(48));
+		player.play(&sheet);
+		let clock = || Msg::Rt(SystemRealtime::TimingClock);
+		assert_eq!(
+			player.con.0.drain(..).collect::<Vec<_>>(),
+			vec![
+				Msg::Rt(SystemRealtime::Start),
+				clock(),
+				clock(),
+				clock(),
+				Msg::Midi,
+				clock(),
+				clock(),
+				clock(),
+				Msg::Midi,
+				// Released notes.
+				Msg::Midi,
+				Msg::Midi,
+				Msg::Midi,
+				Msg::Rt(SystemRealtime::Stop),
+			]
+		);
+
+		// Tick 7 rounds up to the first sixteenth note, at tick 12.
+		player.play_from(&sheet, 7);
+		assert_eq!(
+			player.con.0[..3],
+			[
+				Msg::Position(1),
+				Msg::Rt(SystemRealtime::Continue),
+				Msg::Midi,
+			]
+		);
+
+		// Jumping back while running stops the other devices around the song
+		// position.
+		player.con.0.clear();
+		player.play_loop(&sheet, 0..6, Repeat::Times(2));
+		let i = player
+			.con
+			.0
+			.iter()
+			.position(|m| *m == Msg::Position(0))
+			.unwrap();
+		assert_eq!(
+			player.con.0[i - 1..=i + 1],
+			[
+				Msg::Rt(SystemRealtime::Stop),
+				Msg::Position(0),
+				Msg::Rt(SystemRealtime::Continue),
+			]
+		);
+	}
+}
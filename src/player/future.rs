@@ -0,0 +1,410 @@
+use std::{collections::VecDeque, future::Future, iter::Peekable, time::Duration};
+
+use midly::live::SystemRealtime;
+
+use super::{chase, Connection, Player};
+use crate::{ChaseState, MidiEvent, Timeline, TimelineIter, Timer};
+
+/// Provides the sleep future used by [Player::play_async], so that any async
+/// runtime can be used.
+///
+/// This trait is implemented for every `FnMut(Duration) -> impl Future`, so
+/// you can pass the sleep function of your runtime directly, for example
+/// `tokio::time::sleep`.
+pub trait Sleep {
+	/// The future returned by [Sleep::sleep].
+	type Future: Future<Output = ()>;
+
+	/// Returns a future that completes after the given duration.
+	fn sleep(&mut self, duration: Duration) -> Self::Future;
+}
+
+impl<F, Fut> Sleep for F
+where
+	F: FnMut(Duration) -> Fut,
+	Fut: Future<Output = ()>,
+{
+	type Future = Fut;
+
+	fn sleep(&mut self, duration: Duration) -> Fut {
+		self(duration)
+	}
+}
+
+/// The asynchronous counterpart of [Connection]: the messages are sent by
+/// awaiting the returned futures.
+///
+/// Wrap it in an [AsyncAdapter] and play with [Player::play_async_awaited].
+pub trait AsyncConnection {
+	/// Plays the given [MidiEvent].
+	///
+	/// If the future resolves to `false`, the playback stops.
+	fn play(&mut self, event: MidiEvent) -> impl Future<Output = bool>;
+
+	/// Sends a system realtime message.
+	///
+	/// The default implementation of this method does nothing.
+	fn send_sys_rt(&mut self, _msg: SystemRealtime) -> impl Future<Output = ()> {
+		async {}
+	}
+
+	/// Sends a system exclusive message or an escape sequence.
+	///
+	/// The default implementation of this method does nothing.
+	fn send_sys_ex(&mut self, _data: &[u8]) -> impl Future<Output = ()> {
+		async {}
+	}
+}
+
+enum Message {
+	Midi(MidiEvent),
+	SysRt(SystemRealtime),
+	SysEx(Vec<u8>),
+}
+
+/// A [Connection] that queues the messages for an [AsyncConnection].
+///
+/// [Player::play_async_awaited] sends the queued messages by awaiting the
+/// [AsyncConnection] after every moment. System common messages and text
+/// events are not forwarded.
+pub struct AsyncAdapter<A> {
+	/// The wrapped connection.
+	pub con: A,
+	queue: VecDeque<Message>,
+}
+
+impl<A> AsyncAdapter<A> {
+	/// Wraps the given [AsyncConnection].
+	pub fn new(con: A) -> Self {
+		Self {
+			con,
+			queue: VecDeque::new(),
+		}
+	}
+
+	/// Returns the wrapped connection, dropping the queued messages.
+	pub fn into_inner(self) -> A {
+		self.con
+	}
+}
+
+impl<A: AsyncConnection> AsyncAdapter<A> {
+	/// Sends the queued messages, returning `false` if one was rejected.
+	///
+	/// If a playback is cancelled, the notes are turned off by queueing
+	/// messages that can't be awaited anymore; call this to send them.
+	pub async fn flush(&mut self) -> bool {
+		while let Some(msg) = self.queue.pop_front() {
+			match msg {
+				Message::Midi(e) => {
+					if !self.con.play(e).await {
+						return false;
+					}
+				}
+				Message::SysRt(msg) => self.con.send_sys_rt(msg).await,
+				Message::SysEx(data) => self.con.send_sys_ex(&data).await,
+			}
+		}
+		true
+	}
+}
+
+impl<A> Connection for AsyncAdapter<A> {
+	fn play(&mut self, event: MidiEvent) -> bool {
+		self.queue.push_back(Message::Midi(event));
+		true
+	}
+
+	fn send_sys_rt(&mut self, msg: SystemRealtime) {
+		self.queue.push_back(Message::SysRt(msg));
+	}
+
+	fn send_sys_ex(&mut self, data: &[u8]) {
+		self.queue.push_back(Message::SysEx(data.to_vec()));
+	}
+}
+
+// Sends what the connection queued while playing; a no-op for connections
+// that send right away.
+trait Drain<C> {
+	async fn drain(con: &mut C) -> bool;
+}
+
+struct Immediate;
+
+impl<C> Drain<C> for Immediate {
+	async fn drain(_: &mut C) -> bool {
+		true
+	}
+}
+
+struct Queued;
+
+impl<A: AsyncConnection> Drain<AsyncAdapter<A>> for Queued {
+	async fn drain(con: &mut AsyncAdapter<A>) -> bool {
+		con.flush().await
+	}
+}
+
+// Stops the playback when dropped, even if the future playing is cancelled.
+struct StopGuard<'p, T: Timer, C: Connection> {
+	player: &'p mut Player<T, C>,
+	done: bool,
+}
+
+impl<T: Timer, C: Connection> Drop for StopGuard<'_, T, C> {
+	fn drop(&mut self) {
+		self.player.stopped(self.done);
+	}
+}
+
+impl<T: Timer, C: Connection> Player<T, C> {
+	/// Plays the given track without blocking the thread; the same as
+	/// [Player::play], except that the sleeps are done by awaiting the futures
+	/// returned by `sleep`.
+	///
+	/// The playback can be cancelled by dropping the returned future; the
+	/// sounding notes are turned off then, as with a normal stop.
+	///
+	/// # Notes
+	/// Only [Timer::sleep_duration] is used, so timers that block in
+	/// [Timer::sleep] such as [ExternalClock](crate::timers::ExternalClock)
+	/// are not suitable. [Connection::play] should not block either.
+	///
+	/// # Examples
+	/// ```ignore
+	/// // With tokio:
+	/// let (sheet, timer) = Sheet::open("song.mid")?;
+	/// let mut player = Player::new(timer, con);
+	/// player.play_async(&sheet, tokio::time::sleep).await;
+	/// ```
+	pub async fn play_async<'a, S, Sl>(&mut self, sheet: S, sleep: Sl) -> bool
+	where
+		S: Into<Timeline<'a>>,
+		Sl: Sleep,
+	{
+		self.play_from_async(sheet, 0, sleep).await
+	}
+
+	/// Plays the given track starting from `tick` without blocking the thread.
+	///
+	/// See [Player::play_from] and [Player::play_async].
+	pub async fn play_from_async<'a, S, Sl>(&mut self, sheet: S, tick: u32, sleep: Sl) -> bool
+	where
+		S: Into<Timeline<'a>>,
+		Sl: Sleep,
+	{
+		self.run_async::<_, _, Immediate>(sheet, tick, sleep).await
+	}
+
+	async fn run_async<'a, S, Sl, D>(&mut self, sheet: S, tick: u32, mut sleep: Sl) -> bool
+	where
+		S: Into<Timeline<'a>>,
+		Sl: Sleep,
+		D: Drain<C>,
+	{
+		let mut moments = sheet.into().iter().peekable();
+		let state = chase(&mut moments, tick);
+
+		let done = {
+			let mut guard = StopGuard {
+				player: self,
+				done: false,
+			};
+			let done = guard
+				.player
+				.play_moments_async::<_, D>(state, moments, tick, &mut sleep)
+				.await;
+			guard.done = done;
+			done
+		};
+		// Send what was queued by stopping.
+		D::drain(&mut self.con).await && done
+	}
+
+	async fn play_moments_async<Sl: Sleep, D: Drain<C>>(
+		&mut self,
+		state: ChaseState,
+		moments: Peekable<TimelineIter<'_>>,
+		tick: u32,
+		sleep: &mut Sl,
+	) -> bool {
+		self.started(tick);
+		if !state.events().iter().all(|e| self.handle(e)) || !D::drain(&mut self.con).await {
+			return false;
+		}
+
+		let mut last_tick = tick;
+		for (tick, moment) in moments {
+			if !self.sleep_until_async::<_, D>(last_tick, tick, sleep).await {
+				return false;
+			}
+			last_tick = tick;
+			if !self.play_moment(tick, moment) || !D::drain(&mut self.con).await {
+				return false;
+			}
+		}
+		true
+	}
+
+	// The same as sleep_until, but awaits instead of blocking.
+	async fn sleep_until_async<Sl: Sleep, D: Drain<C>>(
+		&mut self,
+		from: u32,
+		to: u32,
+		sleep: &mut Sl,
+	) -> bool {
+		let mut tick = from;
+		while let Some(pulse) = self.next_pulse().filter(|p| *p < to) {
+			if pulse > tick {
+				self.sleep_ticks_async(pulse - tick, sleep).await;
+				tick = pulse;
+			}
+			self.pulse();
+			if !D::drain(&mut self.con).await {
+				return false;
+			}
+		}
+		self.sleep_ticks_async(to - tick, sleep).await;
+		true
+	}
+
+	async fn sleep_ticks_async<Sl: Sleep>(&mut self, n_ticks: u32, sleep: &mut Sl) {
+		let t = self.timer.sleep_duration(n_ticks);
+		if !t.is_zero() {
+			sleep.sleep(t).await;
+		}
+	}
+}
+
+impl<T: Timer, A: AsyncConnection> Player<T, AsyncAdapter<A>> {
+	/// Plays the given track without blocking the thread, awaiting the sends
+	/// of the [AsyncConnection] as well as the sleeps.
+	///
+	/// See [Player::play_async].
+	pub async fn play_async_awaited<'a, S, Sl>(&mut self, sheet: S, sleep: Sl) -> bool
+	where
+		S: Into<Timeline<'a>>,
+		Sl: Sleep,
+	{
+		self.play_from_async_awaited(sheet, 0, sleep).await
+	}
+
+	/// Plays the given track starting from `tick`, awaiting the sends of the
+	/// [AsyncConnection] as well as the sleeps.
+	///
+	/// See [Player::play_from] and [Player::play_async_awaited].
+	pub async fn play_from_async_awaited<'a, S, Sl>(
+		&mut self,
+		sheet: S,
+		tick: u32,
+		sleep: Sl,
+	) -> bool
+	where
+		S: Into<Timeline<'a>>,
+		Sl: Sleep,
+	{
+		self.run_async::<_, _, Queued>(sheet, tick, sleep).await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		cell::RefCell,
+		future::{self, Future},
+		pin::pin,
+		task::{Context, Poll, Waker},
+	};
+
+	use super::*;
+	use crate::{
+		test_support::{controller, off, on, Capture},
+		timers::FixedTempo,
+		Event, Sheet,
+	};
+
+	#[test]
+	fn play_async() {
+		let on = on(0, 60, 100);
+		let mut sheet = Sheet::new();
+		sheet.extend((0..10).map(|_| Default::default()));
+		sheet[0].push(Event::Midi(on));
+		sheet[4].push(Event::Midi(on));
+
+		let mut cx = Context::from_waker(Waker::noop());
+		let mut player = Player::new(FixedTempo(1000), Capture::default());
+
+		let mut slept = Vec::new();
+		let fut = player.play_async(&sheet, |d| {
+			slept.push(d);
+			future::ready(())
+		});
+		assert_eq!(pin!(fut).poll(&mut cx), Poll::Ready(true));
+		assert_eq!(slept, vec![Duration::from_millis(4)]);
+		// Two notes, two NoteOffs and an All Notes Off.
+		assert_eq!(player.con.0.len(), 5);
+
+		// Cancelled while sleeping.
+		player.con.0.clear();
+		{
+			let mut fut = pin!(player.play_async(&sheet, |_| future::pending()));
+			assert_eq!(fut.as_mut().poll(&mut cx), Poll::Pending);
+		}
+		assert_eq!(player.con.0, vec![on, off(0, 60), controller(0, 123, 0)]);
+	}
+
+	#[derive(Debug, PartialEq)]
+	enum Log {
+		Sleep(Duration),
+		Midi(MidiEvent),
+	}
+
+	struct Logger<'l>(&'l RefCell<Vec<Log>>);
+
+	impl AsyncConnection for Logger<'_> {
+		async fn play(&mut self, event: MidiEvent) -> bool {
+			self.0.borrow_mut().push(Log::Midi(event));
+			true
+		}
+	}
+
+	#[test]
+	fn play_async_awaited() {
+		let on = on(0, 60, 100);
+		let off = off(0, 60);
+		let all_off = controller(0, 123, 0);
+		let mut sheet = Sheet::new();
+		sheet.extend((0..10).map(|_| Default::default()));
+		sheet[0].push(Event::Midi(on));
+		sheet[4].push(Event::Midi(off));
+
+		let mut cx = Context::from_waker(Waker::noop());
+		let log = RefCell::new(Vec::new());
+		let mut player = Player::new(FixedTempo(1000), AsyncAdapter::new(Logger(&log)));
+
+		let fut = player.play_async_awaited(&sheet, |d| {
+			log.borrow_mut().push(Log::Sleep(d));
+			future::ready(())
+		});
+		assert_eq!(pin!(fut).poll(&mut cx), Poll::Ready(true));
+		// The sends are awaited in order with the sleeps.
+		assert_eq!(
+			log.take(),
+			vec![
+				Log::Midi(on),
+				Log::Sleep(Duration::from_millis(4)),
+				Log::Midi(off),
+			]
+		);
+
+		// Cancelled while sleeping: the notes off are queued for flushing.
+		{
+			let mut fut = pin!(player.play_async_awaited(&sheet, |_| future::pending()));
+			assert_eq!(fut.as_mut().poll(&mut cx), Poll::Pending);
+		}
+		assert_eq!(log.take(), vec![Log::Midi(on)]);
+		assert_eq!(pin!(player.con.flush()).poll(&mut cx), Poll::Ready(true));
+		assert_eq!(log.take(), vec![Log::Midi(off), Log::Midi(all_off)]);
+	}
+}
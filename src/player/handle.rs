@@ -0,0 +1,471 @@
+use std::{
+	sync::{
+		atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+		mpsc::{self, Receiver, RecvTimeoutError, Sender},
+		Arc,
+	},
+	thread::{self, JoinHandle},
+	time::{Duration, Instant},
+};
+
+use midly::live::SystemRealtime;
+
+use super::{Connection, Player, Playlist};
+use crate::{timers::sleep, SparseSheet, Timer};
+
+// The last stretch of every wait is slept without watching for commands, so
+// that the timing is as precise as with Timer::sleep.
+const SLEEP_MARGIN: Duration = Duration::from_millis(15);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Command {
+	Pause,
+	Resume,
+	Stop,
+	Seek(u32),
+	Speed(f32),
+	Next,
+	Previous,
+}
+
+// How the playback of a song ended.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum End {
+	Done,
+	Stop,
+	Next,
+	Previous,
+}
+
+impl From<Command> for End {
+	fn from(cmd: Command) -> Self {
+		match cmd {
+			Command::Next => Self::Next,
+			Command::Previous => Self::Previous,
+			_ => Self::Stop,
+		}
+	}
+}
+
+/// A handle to a [Player] playing on a background thread.
+///
+/// This `struct` is created by [Player::spawn] and [Player::spawn_playlist].
+/// Dropping it stops the playback.
+///
+/// # Examples
+/// ```no_run
+/// use std::{thread, time::Duration};
+/// use nodi::{Player, Sheet};
+///
+/// # struct Device;
+/// # impl nodi::Connection for Device { fn play(&mut self, _: nodi::MidiEvent) -> bool { true } }
+/// # let con = Device;
+/// let (sheet, timer) = Sheet::open("song.mid")?;
+/// let handle = Player::new(timer, con).spawn(sheet);
+///
+/// thread::sleep(Duration::from_secs(5));
+/// handle.pause();
+/// println!("paused at tick {}", handle.position());
+/// handle.seek(0);
+/// handle.set_speed(1.5);
+/// handle.resume();
+///
+/// let (player, played_through) = handle.wait();
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct PlaybackHandle<T: Timer, C: Connection> {
+	commands: Sender<Command>,
+	position: Arc<AtomicU32>,
+	song: Arc<AtomicUsize>,
+	paused: Arc<AtomicBool>,
+	thread: JoinHandle<(Player<T, C>, bool)>,
+}
+
+impl<T, C> Player<T, C>
+where
+	T: Timer + Send + 'static,
+	C: Connection + Send + 'static,
+{
+	/// Plays the given [Sheet](crate::Sheet) or [SparseSheet] on a new
+	/// thread, returning a [PlaybackHandle] to control it.
+	///
+	/// Playback starts right away. Since the timing is done by the background
+	/// thread, only [Timer::sleep_duration] is used; [Timer::sleep] is never
+	/// called.
+	pub fn spawn<S: Into<SparseSheet>>(self, sheet: S) -> PlaybackHandle<T, C> {
+		let sheet = sheet.into();
+		let end = sheet.moments.last().map_or(0, |(t, _)| *t);
+
+		PlaybackHandle::spawn(self, move |playback| {
+			playback.player.started(0);
+			let done = loop {
+				match playback.run(&sheet, end) {
+					End::Done => break true,
+					// There is no previous song, the track is restarted.
+					End::Previous if playback.seek(&sheet, 0) => (),
+					_ => break false,
+				}
+			};
+			playback.player.stopped(done)
+		})
+	}
+
+	/// Plays the songs of a [Playlist] on a new thread, returning a
+	/// [PlaybackHandle] to control it.
+	///
+	/// This is the same as [Player::play_playlist], except that the playback
+	/// can be controlled; [PlaybackHandle::next] and
+	/// [PlaybackHandle::previous] skip between the songs.
+	pub fn spawn_playlist(self, playlist: Playlist<T>) -> PlaybackHandle<T, C>
+	where
+		T: Clone,
+	{
+		PlaybackHandle::spawn(self, move |playback| playback.run_playlist(&playlist))
+	}
+}
+
+impl<T, C> PlaybackHandle<T, C>
+where
+	T: Timer + Send + 'static,
+	C: Connection + Send + 'static,
+{
+	// Runs `f` on a new thread; it returns whether the playback is done.
+	fn spawn<F>(player: Player<T, C>, f: F) -> Self
+	where
+		F: FnOnce(&mut Playback<T, C>) -> bool + Send + 'static,
+	{
+		let (sender, receiver) = mpsc::channel();
+		let position = Arc::new(AtomicU32::new(0));
+		let song = Arc::new(AtomicUsize::new(0));
+		let paused = Arc::new(AtomicBool::new(false));
+
+		let mut playback = Playback {
+			player,
+			commands: receiver,
+			position: Arc::clone(&position),
+			song: Arc::clone(&song),
+			paused: false,
+			speed: 1.0,
+		};
+
+		Self {
+			commands: sender,
+			position,
+			song,
+			paused,
+			thread: thread::spawn(move || {
+				let done = f(&mut playback);
+				(playback.player, done)
+			}),
+		}
+	}
+}
+
+impl<T: Timer, C: Connection> PlaybackHandle<T, C> {
+	fn send(&self, cmd: Command) {
+		// The thread is gone if this fails, there is nothing to control anymore.
+		let _ = self.commands.send(cmd);
+	}
+
+	/// Pauses the playback.
+	pub fn pause(&self) {
+		self.paused.store(true, Ordering::Relaxed);
+		self.send(Command::Pause);
+	}
+
+	/// Resumes the playback after a [pause](Self::pause).
+	pub fn resume(&self) {
+		self.paused.store(false, Ordering::Relaxed);
+		self.send(Command::Resume);
+	}
+
+	/// Returns `true` if the playback is paused.
+	pub fn is_paused(&self) -> bool {
+		self.paused.load(Ordering::Relaxed)
+	}
+
+	/// Stops the playback for good.
+	///
+	/// Use [wait](Self::wait) to get the [Player] back.
+	pub fn stop(&self) {
+		self.send(Command::Stop);
+	}
+
+	/// Jumps to the given tick.
+	///
+	/// The state of the track before `tick` is chased, see
+	/// [Player::play_from]. Seeking does not resume a paused playback.
+	pub fn seek(&self, tick: u32) {
+		self.send(Command::Seek(tick));
+	}
+
+	/// Changes the playback speed; `1.0` is the normal speed, `2.0` twice as
+	/// fast and so on.
+	///
+	/// # Panics
+	/// Panics if `speed` is not a positive number.
+	pub fn set_speed(&self, speed: f32) {
+		assert!(
+			speed > 0.0 && speed.is_finite(),
+			"the playback speed must be a positive number"
+		);
+		self.send(Command::Speed(speed));
+	}
+
+	/// Skips to the next song of the [Playlist].
+	///
+	/// If this is the last song or the track was not started with
+	/// [Player::spawn_playlist], the playback stops.
+	pub fn next(&self) {
+		self.send(Command::Next);
+	}
+
+	/// Goes back to the previous song of the [Playlist].
+	///
+	/// If this is the first song or the track was not started with
+	/// [Player::spawn_playlist], the song is restarted instead.
+	pub fn previous(&self) {
+		self.send(Command::Previous);
+	}
+
+	/// Returns the position of the last played [Moment](crate::Moment), in
+	/// ticks.
+	pub fn position(&self) -> u32 {
+		self.position.load(Ordering::Relaxed)
+	}
+
+	/// Returns the index of the song being played in the [Playlist]; always
+	/// `0` if the track was started with [Player::spawn].
+	pub fn song(&self) -> usize {
+		self.song.load(Ordering::Relaxed)
+	}
+
+	/// Returns `true` if the playback is over, either because the track ended,
+	/// it was [stopped](Self::stop) or [Connection::play] returned `false`.
+	pub fn is_finished(&self) -> bool {
+		self.thread.is_finished()
+	}
+
+	/// Blocks until the playback is over, returning the [Player] and whether
+	/// the track was played through the end.
+	///
+	/// # Panics
+	/// Panics if the playback thread panicked.
+	pub fn wait(self) -> (Player<T, C>, bool) {
+		let Self {
+			commands, thread, ..
+		} = self;
+		let res = thread.join();
+		// Dropping the sender early would stop the playback.
+		drop(commands);
+		res.unwrap_or_else(|e| std::panic::resume_unwind(e))
+	}
+}
+
+struct Playback<T: Timer, C: Connection> {
+	player: Player<T, C>,
+	commands: Receiver<Command>,
+	position: Arc<AtomicU32>,
+	song: Arc<AtomicUsize>,
+	paused: bool,
+	speed: f32,
+}
+
+impl<T: Timer, C: Connection> Playback<T, C> {
+	// Plays `sheet` until the tick `end`, which must not come before its last moment.
+	fn run(&mut self, sheet: &SparseSheet, end: u32) -> End {
+		// Index of the next moment.
+		let mut index = 0;
+		let mut tick = 0;
+		// When `tick` was reached.
+		let mut reference = Instant::now();
+		self.position.store(0, Ordering::Relaxed);
+
+		'moments: loop {
+			let moment_tick = match sheet.moments.get(index) {
+				Some((t, _)) => *t,
+				None if tick < end => end,
+				None => return End::Done,
+			};
+			// Clock pulses are waited for like moments.
+			let next = self
+				.player
+				.next_pulse()
+				.filter(|p| *p < moment_tick)
+				.unwrap_or(moment_tick);
+			// The schedule is kept here, the timer should only compute the nominal duration.
+			self.player.timer.reset();
+			let left = self
+				.player
+				.timer
+				.sleep_duration(next - tick)
+				.div_f32(self.speed);
+
+			match self.wait_until(reference + left) {
+				Ok(t) => {
+					reference = t;
+					self.player.reschedule(next, t);
+				}
+				Err(Command::Seek(to)) => {
+					if !self.seek(sheet, to) {
+						return End::Stop;
+					}
+					index = sheet.moments.partition_point(|(t, _)| *t < to);
+					tick = to;
+					reference = Instant::now();
+					continue 'moments;
+				}
+				Err(cmd) => return cmd.into(),
+			}
+
+			tick = next;
+			if tick < moment_tick {
+				self.player.pulse();
+				continue;
+			}
+			let Some((_, moment)) = sheet.moments.get(index) else {
+				return End::Done;
+			};
+			self.position.store(tick, Ordering::Relaxed);
+			if !self.player.play_moment(tick, moment) {
+				return End::Stop;
+			}
+			index += 1;
+		}
+	}
+
+	// Plays the songs one after the other, returns true if the last one is played through the end.
+	fn run_playlist(&mut self, playlist: &Playlist<T>) -> bool
+	where
+		T: Clone,
+	{
+		let mut index = 0;
+		let mut first = true;
+		let mut done = true;
+
+		while let Some((sheet, timer)) = playlist.songs.get(index) {
+			self.song.store(index, Ordering::Relaxed);
+			if !first && playlist.reset_controllers {
+				self.player.reset_controllers();
+			}
+			first = false;
+			self.player.set_timer(timer.clone());
+
+			self.player.started(0);
+			let mut end = self.run(sheet, sheet.len());
+			done = self.player.stopped(end == End::Done);
+
+			if end == End::Done && index + 1 < playlist.songs.len() {
+				end = self.gap(playlist.gap);
+			}
+			index = match end {
+				End::Done | End::Next => index + 1,
+				End::Previous => index.saturating_sub(1),
+				End::Stop => return false,
+			};
+		}
+
+		done
+	}
+
+	// Waits between two songs; seeking is ignored.
+	fn gap(&mut self, gap: Duration) -> End {
+		let target = Instant::now() + gap;
+		loop {
+			match self.wait_until(target) {
+				Ok(_) => return End::Done,
+				Err(Command::Seek(_)) => (),
+				Err(cmd) => return cmd.into(),
+			}
+		}
+	}
+
+	// Waits until `target`, handling pauses and speed changes in the meantime.
+	// Returns when the wait is over, or the command that interrupted it.
+	fn wait_until(&mut self, mut target: Instant) -> Result<Instant, Command> {
+		let mut left = target.saturating_duration_since(Instant::now());
+
+		loop {
+			let cmd = if self.paused {
+				self.commands.recv().unwrap_or(Command::Stop)
+			} else {
+				left = target.saturating_duration_since(Instant::now());
+				if left <= SLEEP_MARGIN {
+					sleep(left);
+					return Ok(target);
+				}
+				match self.commands.recv_timeout(left - SLEEP_MARGIN) {
+					Ok(cmd) => cmd,
+					Err(RecvTimeoutError::Timeout) => continue,
+					Err(RecvTimeoutError::Disconnected) => Command::Stop,
+				}
+			};
+
+			if !self.paused {
+				left = target.saturating_duration_since(Instant::now());
+			}
+			match cmd {
+				Command::Pause => {
+					self.paused = true;
+					self.player.release_notes();
+					self.player.transport(SystemRealtime::Stop);
+				}
+				Command::Resume => {
+					self.paused = false;
+					self.player.transport(SystemRealtime::Continue);
+				}
+				Command::Speed(speed) => {
+					left = left.mul_f32(self.speed / speed);
+					self.speed = speed;
+				}
+				cmd => return Err(cmd),
+			}
+			target = Instant::now() + left;
+		}
+	}
+
+	// Plays the state of the track before `tick`, returns false if playback should stop.
+	fn seek(&mut self, sheet: &SparseSheet, tick: u32) -> bool {
+		self.position.store(tick, Ordering::Relaxed);
+		self.player
+			.seek(&mut sheet.timeline().iter().peekable(), tick)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		test_support::{controller, off, on, Capture},
+		timers::FixedTempo,
+		Event,
+	};
+
+	#[test]
+	fn pause_and_seek() {
+		let note = |key: u8| on(0, key, 100);
+		// Sent when the notes are released.
+		let release = |key: u8| [off(0, key), controller(0, 123, 0)];
+		let mut sheet = SparseSheet::new();
+		for tick in [0, 50, 100] {
+			sheet.insert(tick, Event::Midi(note(tick as u8)));
+		}
+
+		// One millisecond per tick.
+		let handle = Player::new(FixedTempo(1000), Capture::default()).spawn(sheet);
+		handle.pause();
+		thread::sleep(Duration::from_millis(80));
+		assert_eq!(handle.position(), 0);
+		assert!(!handle.is_finished());
+
+		handle.seek(100);
+		handle.resume();
+		let (player, done) = handle.wait();
+		assert!(done);
+		assert_eq!(
+			player.con.0,
+			[&[note(0)], &release(0)[..], &[note(100)], &release(100)].concat()
+		);
+	}
+}
@@ -0,0 +1,166 @@
+use midly::{
+	num::{u4, u7},
+	MidiMessage,
+};
+
+use crate::MidiEvent;
+
+/// Keeps track of the notes that are currently sounding, per channel.
+///
+/// [Player](crate::Player) uses this to turn off exactly the notes that need
+/// to be turned off when the playback stops, instead of resorting to
+/// [Connection::all_notes_off](crate::Connection::all_notes_off).
+///
+/// A note is sounding after a `NoteOn` message until the matching `NoteOff`
+/// (or a `NoteOn` with a velocity of 0). The sustain pedal (controller 64) is
+/// also tracked, since it keeps notes sounding after their `NoteOff`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ActiveNotes {
+	// How many times every key has been pressed without being released.
+	keys: [[u16; 128]; 16],
+	sustain: [bool; 16],
+}
+
+impl Default for ActiveNotes {
+	fn default() -> Self {
+		Self {
+			keys: [[0; 128]; 16],
+			sustain: [false; 16],
+		}
+	}
+}
+
+impl ActiveNotes {
+	/// Creates an empty [ActiveNotes].
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Updates the state with a MIDI message.
+	pub fn update(&mut self, event: &MidiEvent) {
+		let ch = event.channel.as_int() as usize;
+		match event.message {
+			MidiMessage::NoteOn { key, vel } if vel > 0 => {
+				let n = &mut self.keys[ch][key.as_int() as usize];
+				*n = n.saturating_add(1);
+			}
+			MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+				let n = &mut self.keys[ch][key.as_int() as usize];
+				*n = n.saturating_sub(1);
+			}
+			MidiMessage::Controller { controller, value } => match controller.as_int() {
+				64 => self.sustain[ch] = value >= 64,
+				// All Sound Off and All Notes Off.
+				120 | 123 => self.keys[ch] = [0; 128],
+				_ => (),
+			},
+			_ => (),
+		}
+	}
+
+	/// Returns `true` if the given key is held down on the given channel.
+	pub fn is_active(&self, channel: u4, key: u7) -> bool {
+		self.keys[channel.as_int() as usize][key.as_int() as usize] > 0
+	}
+
+	/// Returns `true` if the sustain pedal is down on the given channel.
+	pub fn is_sustained(&self, channel: u4) -> bool {
+		self.sustain[channel.as_int() as usize]
+	}
+
+	/// Returns `true` if no note is held down and no sustain pedal is down.
+	pub fn is_empty(&self) -> bool {
+		self.sustain.iter().all(|s| !s) && self.keys.iter().flatten().all(|n| *n == 0)
+	}
+
+	/// Returns the messages that silence every sounding note, and clears the
+	/// state.
+	///
+	/// A `NoteOff` is returned for every held key, followed by releasing the
+	/// sustain pedal where it's down and an All Notes Off (controller 123) on
+	/// every affected channel. Nothing is returned for the other channels.
+	pub fn release(&mut self) -> Vec<MidiEvent> {
+		(0..16)
+			.flat_map(|ch| self.release_channel(u4::new(ch)))
+			.collect()
+	}
+
+	/// The same as [ActiveNotes::release], but only for the given channel.
+	pub fn release_channel(&mut self, channel: u4) -> Vec<MidiEvent> {
+		let mut buf = Vec::new();
+		let ch = channel.as_int() as usize;
+		let cc = |controller: u8| MidiEvent {
+			channel,
+			message: MidiMessage::Controller {
+				controller: controller.into(),
+				value: 0.into(),
+			},
+		};
+
+		for (key, n) in self.keys[ch].iter_mut().enumerate() {
+			for _ in 0..*n {
+				buf.push(MidiEvent {
+					channel,
+					message: MidiMessage::NoteOff {
+						key: (key as u8).into(),
+						vel: 64.into(),
+					},
+				});
+			}
+			*n = 0;
+		}
+
+		if self.sustain[ch] {
+			self.sustain[ch] = false;
+			buf.push(cc(64));
+		}
+		if !buf.is_empty() {
+			buf.push(cc(123));
+		}
+
+		buf
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_support::{controller, off, on};
+
+	#[test]
+	fn release() {
+		let on = |ch: u8, key: u8| on(ch, key, 100);
+
+		let mut notes = ActiveNotes::new();
+		for e in [
+			on(0, 60),
+			on(0, 64),
+			off(0, 60),
+			on(3, 40),
+			controller(5, 64, 127),
+			on(5, 30),
+			off(5, 30),
+			on(9, 36),
+			controller(9, 123, 0),
+		] {
+			notes.update(&e);
+		}
+
+		assert!(notes.is_active(u4::new(0), 64.into()));
+		assert!(!notes.is_active(u4::new(0), 60.into()));
+		assert!(notes.is_sustained(u4::new(5)));
+		assert_eq!(
+			notes.release(),
+			vec![
+				off(0, 64),
+				controller(0, 123, 0),
+				off(3, 40),
+				controller(3, 123, 0),
+				controller(5, 64, 0),
+				controller(5, 123, 0),
+			]
+		);
+		assert!(notes.is_empty());
+		assert!(notes.release().is_empty());
+	}
+}
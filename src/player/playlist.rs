@@ -0,0 +1,239 @@
+use std::time::Duration;
+
+use midly::MidiMessage;
+
+use super::{Connection, Player};
+use crate::{timers::sleep, MidiEvent, SparseSheet, Timer};
+
+/// A list of songs played back to back by [Player::play_playlist] or
+/// [Player::spawn_playlist].
+///
+/// Every song has its own [Timer], so songs with different timings can be
+/// mixed. Between two songs:
+/// - The notes that are still sounding are turned off.
+/// - The controllers of every channel are reset (controller 121), unless
+///   [reset_controllers](Self::reset_controllers) is `false`.
+/// - The playback waits for [gap](Self::gap).
+///
+/// Songs are played until their end ([SparseSheet::len]) rather than until
+/// their last event, so with no gap the next song starts exactly where the
+/// previous one ends.
+///
+/// # Examples
+/// ```no_run
+/// use std::time::Duration;
+/// use nodi::{Player, Playlist, Sheet};
+///
+/// # struct Device;
+/// # impl nodi::Connection for Device { fn play(&mut self, _: nodi::MidiEvent) -> bool { true } }
+/// # let con = Device;
+/// let mut playlist = Playlist::new();
+/// for path in ["intro.mid", "song.mid", "outro.mid"] {
+///     let (sheet, timer) = Sheet::open(path)?;
+///     playlist.push(sheet, timer);
+/// }
+/// playlist.gap = Duration::from_secs(2);
+///
+/// let mut player = Player::new(playlist.timer(0).unwrap().clone(), con);
+/// player.play_playlist(&playlist);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Playlist<T: Timer> {
+	pub(super) songs: Vec<(SparseSheet, T)>,
+	/// The silence between two songs.
+	///
+	/// The default value is [Duration::ZERO].
+	pub gap: Duration,
+	/// Whether to reset the controllers of every channel between two songs.
+	///
+	/// The default value is `true`.
+	pub reset_controllers: bool,
+}
+
+impl<T: Timer> Default for Playlist<T> {
+	fn default() -> Self {
+		Self {
+			songs: Vec::new(),
+			gap: Duration::ZERO,
+			reset_controllers: true,
+		}
+	}
+}
+
+impl<T: Timer> Playlist<T> {
+	/// Creates an empty [Playlist].
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds a song to the end of the list, along with the [Timer] to play it
+	/// with.
+	pub fn push<S: Into<SparseSheet>>(&mut self, sheet: S, timer: T) {
+		self.songs.push((sheet.into(), timer));
+	}
+
+	/// Returns the song at `index`, if any.
+	pub fn get(&self, index: usize) -> Option<&SparseSheet> {
+		self.songs.get(index).map(|(sheet, _)| sheet)
+	}
+
+	/// Returns the [Timer] of the song at `index`, if any.
+	pub fn timer(&self, index: usize) -> Option<&T> {
+		self.songs.get(index).map(|(_, timer)| timer)
+	}
+
+	/// Returns the number of songs.
+	pub fn len(&self) -> usize {
+		self.songs.len()
+	}
+
+	/// Returns `Self::len() == 0`.
+	pub fn is_empty(&self) -> bool {
+		self.songs.is_empty()
+	}
+}
+
+impl<T: Timer + Clone, C: Connection> Player<T, C> {
+	/// Plays the songs of a [Playlist] one after the other.
+	///
+	/// Before every song, the timer of the [Player] is replaced with a copy of
+	/// the song's timer. Every song is a separate playback as far as the
+	/// [Observer](crate::Observer) and the MIDI clock are concerned; the MIDI
+	/// clock uses the ticks per beat given to [Player::set_clock] for every
+	/// song.
+	///
+	/// Returns `true` if every song is played through the end, `false`
+	/// otherwise.
+	pub fn play_playlist(&mut self, playlist: &Playlist<T>) -> bool {
+		for (i, (sheet, timer)) in playlist.songs.iter().enumerate() {
+			if i > 0 {
+				if playlist.reset_controllers {
+					self.reset_controllers();
+				}
+				sleep(playlist.gap);
+			}
+			self.set_timer(timer.clone());
+
+			self.started(0);
+			let last = sheet.moments.last().map_or(0, |(t, _)| *t);
+			let timeline = sheet.timeline();
+			let done = self.play_moments(timeline, timeline.iter().peekable(), 0) && {
+				self.sleep_until(last, sheet.len());
+				true
+			};
+			if !self.stopped(done) {
+				return false;
+			}
+		}
+
+		true
+	}
+}
+
+impl<T: Timer, C: Connection> Player<T, C> {
+	// Sends Reset All Controllers on every channel.
+	pub(super) fn reset_controllers(&mut self) {
+		for ch in 0..16 {
+			self.con.play(MidiEvent {
+				channel: ch.into(),
+				message: MidiMessage::Controller {
+					controller: 121.into(),
+					value: 0.into(),
+				},
+			});
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{thread, time::Instant};
+
+	use super::*;
+	use crate::{
+		test_support::{midi, Capture},
+		timers::FixedTempo,
+		Event,
+	};
+
+	fn song(key: u8, len: u32) -> SparseSheet {
+		let mut sheet = SparseSheet::new();
+		let note = |message| Event::Midi(midi(0, message));
+		sheet.insert(
+			0,
+			note(MidiMessage::NoteOn {
+				key: key.into(),
+				vel: 100.into(),
+			}),
+		);
+		sheet.insert(
+			len - 1,
+			note(MidiMessage::NoteOff {
+				key: key.into(),
+				vel: 0.into(),
+			}),
+		);
+		sheet
+	}
+
+	fn keys(events: &[MidiEvent]) -> Vec<u8> {
+		events
+			.iter()
+			.filter_map(|e| match e.message {
+				MidiMessage::NoteOn { key, .. } => Some(key.as_int()),
+				_ => None,
+			})
+			.collect()
+	}
+
+	#[test]
+	fn play_playlist() {
+		let mut playlist = Playlist::new();
+		// The second song is twice as slow.
+		playlist.push(song(1, 20), FixedTempo(1000));
+		playlist.push(song(2, 20), FixedTempo(2000));
+		playlist.gap = Duration::from_millis(30);
+
+		let mut player = Player::new(FixedTempo(0), Capture::default());
+		let now = Instant::now();
+		assert!(player.play_playlist(&playlist));
+		// 20ms, a gap of 30ms and 40ms; the last song is played until its end too.
+		assert!(now.elapsed() >= Duration::from_millis(90));
+		assert_eq!(keys(&player.con.0), [1, 2]);
+		let resets = player
+			.con
+			.0
+			.iter()
+			.filter(
+				|e| matches!(e.message, MidiMessage::Controller { controller, .. } if controller == 121),
+			)
+			.count();
+		assert_eq!(resets, 16);
+	}
+
+	#[test]
+	fn skip() {
+		let mut playlist = Playlist::new();
+		for key in 0..3 {
+			playlist.push(song(key, 1000), FixedTempo(1000));
+		}
+		playlist.reset_controllers = false;
+
+		let handle = Player::new(FixedTempo(0), Capture::default()).spawn_playlist(playlist);
+		thread::sleep(Duration::from_millis(50));
+		handle.next();
+		thread::sleep(Duration::from_millis(50));
+		assert_eq!(handle.song(), 1);
+		handle.previous();
+		thread::sleep(Duration::from_millis(50));
+		assert_eq!(handle.song(), 0);
+		handle.next();
+		handle.next();
+		handle.next();
+		// Skipping the last song stops the playback.
+		let (player, done) = handle.wait();
+		assert!(!done);
+		assert_eq!(keys(&player.con.0), [0, 1, 0, 1, 2]);
+	}
+}
@@ -0,0 +1,186 @@
+use std::time::Duration;
+
+use super::{Connection, Player};
+use crate::{
+	event::TextKind,
+	timers::{VirtualClock, VirtualTime},
+	Event, MidiEvent, Timeline, Timer,
+};
+
+/// A [Connection] that records the events it receives, along with the time
+/// of a [VirtualClock].
+///
+/// Together, they play a track instantly and produce a log of every event and
+/// the exact time it would be played at; use [Recorder::render] for the common
+/// case.
+///
+/// MIDI messages, SysEx messages, escape sequences and text events are
+/// recorded. Since
+/// [Event] cannot represent them, system realtime and system common messages
+/// (such as the MIDI clock) are not.
+///
+/// # Examples
+/// ```
+/// use std::time::Duration;
+/// use nodi::{timers::{Ticker, VirtualClock}, Player, Recorder, Sheet};
+///
+/// let sheet = Sheet::new();
+/// let clock = VirtualClock::new(Ticker::new(480));
+/// let recorder = Recorder::new(clock.time());
+/// let mut player = Player::new(clock, recorder);
+/// player.play(&sheet);
+///
+/// for (time, event) in player.con.events() {
+///     println!("{:?}: {:?}", time, event);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Recorder {
+	time: VirtualTime,
+	events: Vec<(Duration, Event)>,
+}
+
+impl Recorder {
+	/// Creates a [Recorder] that timestamps the events with `time`, obtained
+	/// from [VirtualClock::time].
+	pub fn new(time: VirtualTime) -> Self {
+		Self {
+			time,
+			events: Vec::new(),
+		}
+	}
+
+	/// Plays the given track instantly, returning every event with the time it
+	/// would be played at.
+	///
+	/// `timer` computes the durations, the same one that would be used to play
+	/// the track in real time. The notes that are still sounding at the end are
+	/// turned off, as with [Player::play].
+	pub fn render<'a, S, T>(sheet: S, timer: T) -> Vec<(Duration, Event)>
+	where
+		S: Into<Timeline<'a>>,
+		T: Timer,
+	{
+		let clock = VirtualClock::new(timer);
+		let recorder = Self::new(clock.time());
+		let mut player = Player::new(clock, recorder);
+		player.play(sheet);
+		player.con.into_events()
+	}
+
+	/// Returns the recorded events.
+	pub fn events(&self) -> &[(Duration, Event)] {
+		&self.events
+	}
+
+	/// Consumes `self` and returns the recorded events.
+	pub fn into_events(self) -> Vec<(Duration, Event)> {
+		self.events
+	}
+
+	/// Clears the recorded events.
+	pub fn clear(&mut self) {
+		self.events.clear();
+	}
+
+	fn record(&mut self, event: Event) {
+		self.events.push((self.time.get(), event));
+	}
+}
+
+impl Connection for Recorder {
+	fn play(&mut self, event: MidiEvent) -> bool {
+		self.record(Event::Midi(event));
+		true
+	}
+
+	fn send_sys_ex(&mut self, data: &[u8]) {
+		self.record(Event::SysEx(data.to_vec()));
+	}
+
+	fn send_escape(&mut self, data: &[u8]) {
+		self.record(Event::Escape(data.to_vec()));
+	}
+
+	fn send_text(&mut self, kind: TextKind, text: &[u8]) {
+		self.record(Event::Text(kind, text.to_vec()));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use midly::MidiMessage;
+
+	use super::*;
+	use crate::{timers::Ticker, Sheet};
+
+	#[test]
+	fn render() {
+		let note = |key: u8| {
+			Event::Midi(MidiEvent {
+				channel: 0.into(),
+				message: MidiMessage::NoteOn {
+					key: key.into(),
+					vel: 100.into(),
+				},
+			})
+		};
+		let mut sheet = Sheet::new();
+		sheet.extend((0..2000).map(|_| Default::default()));
+		// 120 BPM, then 60 BPM after a beat.
+		sheet[0].push(Event::Tempo(500_000));
+		sheet[0].push(note(60));
+		sheet[480].push(Event::Tempo(1_000_000));
+		sheet[480].push(Event::Text(TextKind::Marker, b"slow".to_vec()));
+		sheet[960].push(note(62));
+		sheet[1920].push(note(64));
+
+		let events = Recorder::render(&sheet, Ticker::new(480));
+		let ms = Duration::from_millis;
+		let times = events.iter().map(|(t, _)| *t).collect::<Vec<_>>();
+		// The last four are the released notes.
+		assert_eq!(
+			times,
+			[
+				ms(0),
+				ms(500),
+				ms(1500),
+				ms(3500),
+				ms(3500),
+				ms(3500),
+				ms(3500),
+				ms(3500)
+			]
+		);
+		assert_eq!(events[1].1, Event::Text(TextKind::Marker, b"slow".to_vec()));
+		assert_eq!(events[3].1, note(64));
+	}
+
+	#[test]
+	fn sys_ex() {
+		let note = Event::Midi(MidiEvent {
+			channel: 0.into(),
+			message: MidiMessage::ProgramChange { program: 5.into() },
+		});
+		let sys_ex = Event::SysEx(vec![0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7]);
+		let split = Event::SysEx(vec![0xF0, 0x43, 0x10]);
+		let escape = Event::Escape(vec![0x4C, 0x00, 0xF7]);
+		// An escape sequence holding a whole SysEx message.
+		let escaped = Event::Escape(vec![0xF0, 0x7E, 0xF7]);
+		let mut sheet = Sheet::new();
+		sheet.extend((0..10).map(|_| Default::default()));
+		sheet[0].push(sys_ex.clone());
+		sheet[0].push(note.clone());
+		sheet[5].push(split.clone());
+		sheet[6].push(escape.clone());
+		sheet[7].push(escaped.clone());
+
+		// SysEx messages and escapes reach the connection in order, and keep
+		// their kind.
+		let events = Recorder::render(&sheet, Ticker::new(480))
+			.into_iter()
+			.map(|(_, e)| e)
+			.collect::<Vec<_>>();
+		assert_eq!(events, [sys_ex, note, split, escape, escaped]);
+	}
+}
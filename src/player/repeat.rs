@@ -0,0 +1,191 @@
+use std::{ops::Range, time::Instant};
+
+use super::{Connection, Player};
+use crate::{sheet::bar_range, ChaseState, Timeline, Timer};
+
+/// How many times a region is played by [Player::play_loop].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Repeat {
+	/// Play the region the given number of times.
+	Times(u32),
+	/// Play the region until [Connection::play] returns `false`.
+	Forever,
+}
+
+impl<T: Timer, C: Connection> Player<T, C> {
+	/// Plays the ticks in `range` over and over.
+	///
+	/// The state of the track at `range.start` is chased before every pass,
+	/// as with [Player::play_from], so that every pass sounds the same. The
+	/// tempo of the timer (see [Timer::tempo]) is restored too. Notes that are
+	/// still on when the end of the region is reached are turned off.
+	///
+	/// `range.end` is clamped to the length of the track.
+	///
+	/// Returns `true` if every pass is played through the end, `false`
+	/// otherwise.
+	pub fn play_loop<'a, S: Into<Timeline<'a>>>(
+		&mut self,
+		sheet: S,
+		range: Range<u32>,
+		repeat: Repeat,
+	) -> bool {
+		let timeline = sheet.into();
+		let start = range.start;
+		let end = range.end.min(timeline.len());
+		if start >= end {
+			return true;
+		}
+
+		let state = ChaseState::at(timeline, start).events();
+		let tempo = self.timer.tempo();
+		let mut pass = 0;
+		self.started(start);
+
+		while repeat != Repeat::Times(pass) {
+			// A tempo change inside the region must not carry over to the next pass.
+			if pass > 0 {
+				self.reschedule(start, Instant::now());
+				self.locate(start);
+				if let Some(tempo) = tempo {
+					self.timer.change_tempo(tempo);
+				}
+			}
+			if !state.iter().all(|e| self.handle(e)) {
+				return self.stopped(false);
+			}
+
+			let mut last_tick = start;
+			let moments = timeline
+				.iter()
+				.skip_while(|(t, _)| *t < start)
+				.take_while(|(t, _)| *t < end);
+			for (tick, moment) in moments {
+				self.sleep_until(last_tick, tick);
+				last_tick = tick;
+
+				if !self.play_moment(tick, moment) {
+					return self.stopped(false);
+				}
+			}
+
+			self.sleep_until(last_tick, end);
+			self.release_notes();
+			pass += 1;
+		}
+
+		self.stopped(true)
+	}
+
+	/// Plays the given bars over and over.
+	///
+	/// Bars are counted from 0 and are the same ones
+	/// [Sheet::into_bars](crate::Sheet::into_bars) would yield. See
+	/// [Player::play_loop] for the details.
+	///
+	/// # Examples
+	/// ```no_run
+	/// use nodi::{Player, Repeat, Sheet};
+	///
+	/// # struct Device;
+	/// # impl nodi::Connection for Device { fn play(&mut self, _: nodi::MidiEvent) -> bool { true } }
+	/// # let con = Device;
+	/// let (sheet, timer) = Sheet::open("song.mid")?;
+	/// let mut player = Player::new(timer, con);
+	/// // Practice bars 17 to 32 (as counted by musicians) 4 times.
+	/// player.play_loop_bars(&sheet, 480, 16..32, Repeat::Times(4));
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn play_loop_bars<'a, S: Into<Timeline<'a>>>(
+		&mut self,
+		sheet: S,
+		ticks_per_beat: u16,
+		bars: Range<usize>,
+		repeat: Repeat,
+	) -> bool {
+		let timeline = sheet.into();
+		match bar_range(timeline, ticks_per_beat, bars) {
+			Some(range) => self.play_loop(timeline, range, repeat),
+			None => true,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use midly::MidiMessage;
+
+	use std::time::Duration;
+
+	use super::*;
+	use crate::{
+		test_support::{controller, midi, off, on, Capture},
+		timers::{FixedTempo, Ticker, VirtualClock},
+		Event, MidiEvent, Recorder, Sheet,
+	};
+
+	#[test]
+	fn play_loop() {
+		let program = midi(2, MidiMessage::ProgramChange { program: 3.into() });
+		let on = |key: u8| on(2, key, 90);
+		let off = off(2, 60);
+		let notes_off = controller(2, 123, 0);
+
+		let mut sheet = Sheet::new();
+		sheet.extend((0..20).map(|_| Default::default()));
+		sheet[0].push(Event::Midi(program));
+		sheet[2].push(Event::Midi(on(50)));
+		sheet[5].push(Event::Midi(on(60)));
+		sheet[15].push(Event::Midi(off));
+
+		let mut player = Player::new(FixedTempo(0), Capture::default());
+		assert!(player.play_loop(&sheet, 5..10, Repeat::Times(2)));
+		assert_eq!(
+			player.con.0,
+			vec![
+				program,
+				on(60),
+				off,
+				notes_off,
+				program,
+				on(60),
+				off,
+				notes_off
+			]
+		);
+	}
+
+	#[test]
+	fn restore_tempo() {
+		let on = |key: u8| Event::Midi(on(0, key, 90));
+		let mut sheet = Sheet::new();
+		sheet.extend((0..10).map(|_| Default::default()));
+		sheet[0].push(on(1));
+		// Twice as slow from the middle of the region on.
+		sheet[5].push(Event::Tempo(200_000));
+		sheet[9].push(on(2));
+
+		// 10ms per tick.
+		let clock = VirtualClock::new(Ticker::with_initial_tempo(10, 100_000));
+		let time = clock.time();
+		let mut player = Player::new(clock, Recorder::new(time));
+		assert!(player.play_loop(&sheet, 0..10, Repeat::Times(2)));
+		let times = player
+			.con
+			.events()
+			.iter()
+			.filter(|(_, e)| {
+				matches!(
+					e,
+					Event::Midi(MidiEvent {
+						message: MidiMessage::NoteOn { .. },
+						..
+					})
+				)
+			})
+			.map(|(t, _)| *t)
+			.collect::<Vec<_>>();
+		let ms = Duration::from_millis;
+		assert_eq!(times, [ms(0), ms(130), ms(150), ms(280)]);
+	}
+}
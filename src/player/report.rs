@@ -0,0 +1,205 @@
+use std::time::{Duration, Instant};
+
+use super::{Connection, Player};
+use crate::Timer;
+
+/// Why a playback stopped, see [PlaybackReport].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum StopReason {
+	/// The track was played through the end.
+	Finished,
+	/// [Connection::play] returned `false`.
+	Connection,
+	/// The playback was interrupted from the outside; for example it was
+	/// stopped through a [PlaybackHandle](crate::PlaybackHandle), the song was
+	/// skipped or the future of [Player::play_async] was dropped.
+	Interrupted,
+}
+
+/// A summary of a playback, returned by [Player::play_report].
+///
+/// # Notes
+/// The lateness of a [Moment](crate::Moment) is how much later than scheduled
+/// it is played. The schedule is computed with [Timer::nominal_duration], so
+/// the time spent paused by a [ControlTicker](crate::timers::ControlTicker)
+/// counts as lateness.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct PlaybackReport {
+	/// Why the playback stopped.
+	pub reason: StopReason,
+	/// The tick the playback started from.
+	pub start_tick: u32,
+	/// The position of the last played [Moment](crate::Moment), if any was
+	/// played.
+	pub last_tick: Option<u32>,
+	/// The wall time the playback took.
+	pub elapsed: Duration,
+	/// The number of events of the track sent to the [Connection], including
+	/// the chased ones; the messages sent to turn off the notes at the end are
+	/// not counted.
+	pub events: usize,
+	/// The number of played [Moment](crate::Moment)s.
+	pub moments: usize,
+	/// The highest lateness of a moment.
+	pub max_lateness: Duration,
+	/// The average lateness of the moments.
+	pub mean_lateness: Duration,
+}
+
+impl PlaybackReport {
+	/// Returns `true` if the track was played through the end.
+	pub fn is_finished(&self) -> bool {
+		self.reason == StopReason::Finished
+	}
+}
+
+// The statistics of the current playback.
+#[derive(Debug, Clone)]
+pub(super) struct Stats {
+	start: Instant,
+	start_tick: u32,
+	last_tick: Option<u32>,
+	events: usize,
+	moments: usize,
+	refused: bool,
+	total_lateness: Duration,
+	max_lateness: Duration,
+	// The schedule: `tick` is due at `anchor + nominal`.
+	anchor: Instant,
+	nominal: Duration,
+	tick: u32,
+}
+
+impl Stats {
+	pub(super) fn new(tick: u32) -> Self {
+		let now = Instant::now();
+		Self {
+			start: now,
+			start_tick: tick,
+			last_tick: None,
+			events: 0,
+			moments: 0,
+			refused: false,
+			total_lateness: Duration::ZERO,
+			max_lateness: Duration::ZERO,
+			anchor: now,
+			nominal: Duration::ZERO,
+			tick,
+		}
+	}
+
+	pub(super) fn sent(&mut self, accepted: bool) {
+		self.events += 1;
+		self.refused |= !accepted;
+	}
+
+	fn report(&self, done: bool) -> PlaybackReport {
+		let reason = if done {
+			StopReason::Finished
+		} else if self.refused {
+			StopReason::Connection
+		} else {
+			StopReason::Interrupted
+		};
+
+		PlaybackReport {
+			reason,
+			start_tick: self.start_tick,
+			last_tick: self.last_tick,
+			elapsed: self.start.elapsed(),
+			events: self.events,
+			moments: self.moments,
+			max_lateness: self.max_lateness,
+			mean_lateness: match self.moments {
+				0 => Duration::ZERO,
+				n => self.total_lateness / n as u32,
+			},
+		}
+	}
+}
+
+impl<T: Timer, C: Connection> Player<T, C> {
+	/// Returns the report of the last playback, if any.
+	///
+	/// Every kind of playback produces one, including
+	/// [Player::play_loop], [Player::play_async] and [Player::spawn].
+	pub fn last_report(&self) -> Option<&PlaybackReport> {
+		self.report.as_ref()
+	}
+
+	// Schedules `tick` at `at`; used when the playback jumps or is timed from elsewhere.
+	pub(super) fn reschedule(&mut self, tick: u32, at: Instant) {
+		self.stats.anchor = at;
+		self.stats.nominal = Duration::ZERO;
+		self.stats.tick = tick;
+	}
+
+	// Records that the moment at `tick` is being played.
+	pub(super) fn record_moment(&mut self, tick: u32) {
+		let stats = &mut self.stats;
+		stats.nominal += self.timer.nominal_duration(tick.saturating_sub(stats.tick));
+		stats.tick = tick;
+
+		let lateness = Instant::now().saturating_duration_since(stats.anchor + stats.nominal);
+		stats.total_lateness += lateness;
+		stats.max_lateness = stats.max_lateness.max(lateness);
+		stats.moments += 1;
+		stats.last_tick = Some(tick);
+	}
+
+	// Ends the current playback, returning its report.
+	pub(super) fn finish(&mut self, done: bool) -> PlaybackReport {
+		let report = self.stats.report(done);
+		self.report = Some(report);
+		report
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use midly::MidiMessage;
+
+	use super::*;
+	use crate::{timers::FixedTempo, Event, MidiEvent, Sheet};
+
+	struct Refuse(usize);
+
+	impl Connection for Refuse {
+		fn play(&mut self, _: MidiEvent) -> bool {
+			self.0 = self.0.saturating_sub(1);
+			self.0 > 0
+		}
+	}
+
+	#[test]
+	fn report() {
+		let note = Event::Midi(MidiEvent {
+			channel: 0.into(),
+			message: MidiMessage::NoteOn {
+				key: 60.into(),
+				vel: 100.into(),
+			},
+		});
+		let mut sheet = Sheet::new();
+		sheet.extend((0..40).map(|_| Default::default()));
+		for tick in [0, 10, 20, 30] {
+			sheet[tick].push(note.clone());
+		}
+
+		let mut player = Player::new(FixedTempo(1000), Refuse(usize::MAX));
+		let report = player.play_report(&sheet);
+		assert!(report.is_finished());
+		assert_eq!(report.last_tick, Some(30));
+		assert_eq!((report.events, report.moments), (4, 4));
+		assert!(report.elapsed >= Duration::from_millis(30));
+		assert!(report.mean_lateness <= report.max_lateness);
+
+		player.con = Refuse(2);
+		assert!(!player.play_from(&sheet, 5));
+		let report = player.last_report().unwrap();
+		assert_eq!(report.reason, StopReason::Connection);
+		assert_eq!((report.start_tick, report.last_tick), (5, Some(20)));
+		// Notes are not chased, the first note played is at tick 10.
+		assert_eq!(report.events, 2);
+	}
+}
@@ -0,0 +1,207 @@
+use std::io::{self, Write};
+
+use midly::live::{LiveEvent, SystemCommon, SystemRealtime};
+
+use super::Connection;
+use crate::MidiEvent;
+
+/// A [Connection] that writes raw MIDI bytes to any [io::Write], such as a
+/// file, a pipe or a serial device.
+///
+/// Every message is written as it would be sent over a MIDI cable:
+/// - Channel messages, optionally with running status, see
+///   [running_status](Self::running_status).
+/// - System realtime messages, which may be interleaved with anything and do
+///   not affect the running status.
+/// - System common messages and SysEx messages, which cancel the running
+///   status.
+///
+/// Text events are not written.
+///
+/// # Errors
+/// [Connection::play] returns `false` if writing fails, which stops the
+/// [Player](crate::Player). The other methods cannot report errors; in every
+/// case, the first error is kept and can be retrieved with
+/// [take_error](Self::take_error).
+///
+/// # Examples
+/// ```no_run
+/// use std::fs::OpenOptions;
+/// use nodi::{MidiWriter, Player, Sheet};
+///
+/// let (sheet, timer) = Sheet::open("song.mid")?;
+/// // A serial MIDI interface.
+/// let port = OpenOptions::new().write(true).open("/dev/ttyUSB0")?;
+/// let mut con = MidiWriter::new(port);
+/// con.running_status = true;
+///
+/// let mut player = Player::new(timer, con);
+/// player.play(&sheet);
+/// if let Some(e) = player.con.take_error() {
+///     eprintln!("error: {}", e);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct MidiWriter<W: Write> {
+	writer: W,
+	buf: Vec<u8>,
+	// The status byte of the last channel message, if running status is in effect.
+	status: Option<u8>,
+	error: Option<io::Error>,
+	/// Whether to omit the status byte of a channel message when it's the
+	/// same as the previous one.
+	///
+	/// This makes the stream about a third smaller, which matters on slow
+	/// links such as a 31250 baud MIDI cable. The default value is `false`.
+	pub running_status: bool,
+	/// Whether to flush the writer after every message, so that it's
+	/// delivered right away.
+	///
+	/// Disable this when writing to a buffered file. The default value is
+	/// `true`.
+	pub flush: bool,
+}
+
+impl<W: Write> MidiWriter<W> {
+	/// Creates a [MidiWriter] writing to `writer`.
+	pub fn new(writer: W) -> Self {
+		Self {
+			writer,
+			buf: Vec::with_capacity(8),
+			status: None,
+			error: None,
+			running_status: false,
+			flush: true,
+		}
+	}
+
+	/// Returns a reference to the underlying writer.
+	pub fn get_ref(&self) -> &W {
+		&self.writer
+	}
+
+	/// Returns a mutable reference to the underlying writer.
+	///
+	/// Writing to it directly may break the running status; call
+	/// [reset_status](Self::reset_status) afterwards.
+	pub fn get_mut(&mut self) -> &mut W {
+		&mut self.writer
+	}
+
+	/// Consumes `self` and returns the underlying writer.
+	pub fn into_inner(self) -> W {
+		self.writer
+	}
+
+	/// Returns the first error that happened while writing, if any, and
+	/// clears it.
+	pub fn take_error(&mut self) -> Option<io::Error> {
+		self.error.take()
+	}
+
+	/// Makes the next channel message be written with its status byte.
+	///
+	/// Use this if the receiving end may have missed the previous status, for
+	/// example after reconnecting a device.
+	pub fn reset_status(&mut self) {
+		self.status = None;
+	}
+
+	// Writes the message in `self.buf`, skipping its first `skip` bytes.
+	fn send(&mut self, skip: usize) -> bool {
+		let res = self.writer.write_all(&self.buf[skip..]).and_then(|_| {
+			if self.flush {
+				self.writer.flush()
+			} else {
+				Ok(())
+			}
+		});
+
+		match res {
+			Ok(()) => true,
+			Err(e) => {
+				// The receiver may have missed the status byte.
+				self.status = None;
+				self.error.get_or_insert(e);
+				false
+			}
+		}
+	}
+
+	// Writes a realtime, common or SysEx message.
+	fn send_system(&mut self, event: LiveEvent<'_>) {
+		self.buf.clear();
+		if event.write_std(&mut self.buf).is_ok() {
+			self.send(0);
+		}
+	}
+}
+
+impl<W: Write> Connection for MidiWriter<W> {
+	fn play(&mut self, event: MidiEvent) -> bool {
+		self.buf.clear();
+		// Writing to a Vec cannot fail.
+		let _ = event.write(&mut self.buf);
+
+		let status = self.buf[0];
+		let skip = usize::from(self.running_status && self.status == Some(status));
+		self.status = self.running_status.then_some(status);
+		self.send(skip)
+	}
+
+	fn send_sys_rt(&mut self, msg: SystemRealtime) {
+		self.send_system(LiveEvent::Realtime(msg));
+	}
+
+	fn send_sys_common(&mut self, msg: SystemCommon<'_>) {
+		self.status = None;
+		self.send_system(LiveEvent::Common(msg));
+	}
+
+	fn send_sys_ex(&mut self, data: &[u8]) {
+		self.status = None;
+		self.buf.clear();
+		self.buf.extend_from_slice(data);
+		self.send(0);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use midly::num::u14;
+
+	use super::*;
+	use crate::test_support::on;
+
+	#[test]
+	fn running_status() {
+		let on = |ch: u8, key: u8| on(ch, key, 100);
+
+		let mut con = MidiWriter::new(Vec::new());
+		con.running_status = true;
+		con.play(on(0, 60));
+		con.play(on(0, 62));
+		con.send_sys_rt(SystemRealtime::TimingClock);
+		con.play(on(0, 64));
+		con.play(on(1, 64));
+		con.send_sys_common(SystemCommon::SongPosition(u14::new(3)));
+		con.play(on(1, 65));
+		con.send_sys_ex(&[0xF0, 0x7E, 0xF7]);
+		con.play(on(1, 66));
+		con.send_text(crate::TextKind::Lyric, b"la");
+
+		assert_eq!(
+			con.into_inner(),
+			[
+				0x90, 60, 100, 62, 100, 0xF8, 64, 100, 0x91, 64, 100, 0xF2, 3, 0, 0x91, 65, 100,
+				0xF0, 0x7E, 0xF7, 0x91, 66, 100,
+			]
+		);
+
+		let mut con = MidiWriter::new(Vec::new());
+		con.play(on(0, 60));
+		con.play(on(0, 62));
+		assert_eq!(con.get_ref(), &[0x90, 60, 100, 0x90, 62, 100]);
+	}
+}
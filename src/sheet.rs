@@ -3,13 +3,24 @@ use midly::TrackEvent;
 use crate::event::Moment;
 
 mod bar;
+mod excerpt;
+mod export;
 mod impls;
-
-pub use bar::Bars;
+mod load;
+mod sparse;
+mod timeline;
+mod tracks;
+
+pub(crate) use bar::bar_range;
+pub use bar::{Bar, Bars};
+pub use excerpt::Straddle;
+pub use sparse::SparseSheet;
+pub use timeline::{Timeline, TimelineIter};
+use tracks::Tracks;
 
 #[doc = include_str!("doc_sheet.md")]
-#[derive(Default, Clone, Debug, Eq, PartialEq, Hash)]
-pub struct Sheet(pub(crate) Vec<Moment>);
+#[derive(Default, Clone, Debug)]
+pub struct Sheet(pub(crate) Vec<Moment>, pub(crate) Tracks);
 
 impl Sheet {
 	/// Creates a [Sheet] from a slice of [TrackEvent]s.
@@ -23,6 +34,9 @@ impl Sheet {
 
 	/// Creates a [Sheet] from many tracks, merging all of them into one.
 	///
+	/// The index of the track every event originates from is recorded, see
+	/// [Sheet::iter_tracked] and [Sheet::into_tracks].
+	///
 	/// # Notes
 	/// Use this when a MIDI file header specifies the format to be of 1,
 	/// meaning parallel.
@@ -33,8 +47,8 @@ impl Sheet {
 
 		let mut first = Self::from(tracks[0].as_slice());
 
-		for track in &tracks[1..] {
-			let sh = Self::from(track.as_slice());
+		for (i, track) in tracks.iter().enumerate().skip(1) {
+			let sh = Self::from_track(i as u16, track);
 			first.merge_with(sh);
 		}
 		first
@@ -53,21 +67,22 @@ impl Sheet {
 
 		let mut first = Self::from(tracks[0].as_slice());
 
-		for track in &tracks[1..] {
-			let sh = Self::from(track.as_slice());
-			first.extend(sh);
+		for (i, track) in tracks.iter().enumerate().skip(1) {
+			let sh = Self::from_track(i as u16, track);
+			first.1.append(sh.1, first.0.len() as u32);
+			first.0.extend(sh.0);
 		}
 		first
 	}
 
 	/// Creates a new, blank [Sheet].
 	pub fn new() -> Self {
-		Self(Vec::new())
+		Self(Vec::new(), Tracks::default())
 	}
 
 	/// Creates a new, blank [Sheet] with the given initial capacity.
 	pub fn with_capacity(cap: usize) -> Self {
-		Self(Vec::with_capacity(cap))
+		Self(Vec::with_capacity(cap), Tracks::default())
 	}
 
 	/// Destroys `self` yielding the underlying [Vec].
@@ -102,10 +117,14 @@ impl Sheet {
 			self.0.extend((0..n_new).map(|_| Moment::default()))
 		}
 
+		let (mine, theirs) = (&self.0, &other.0);
+		self.1.merge(
+			other.1,
+			|tick| mine.get(tick as usize).map_or(0, |m| m.len()),
+			|tick| theirs.get(tick as usize).map_or(0, |m| m.len()),
+		);
 		for (i, moment) in other.0.into_iter().enumerate() {
-			for e in moment.events {
-				self[i].push(e);
-			}
+			self.0[i].extend(moment.events);
 		}
 	}
 
@@ -116,7 +135,11 @@ impl Sheet {
 
 	/// Returns an iterator over mutable references to the [Moment]s contained
 	/// in `self`.
+	///
+	/// The tracks the events originate from are forgotten, see
+	/// [Sheet::iter_tracked].
 	pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Moment> {
+		self.1.forget(0..self.0.len() as u32);
 		self.0.iter_mut()
 	}
 
@@ -130,8 +153,9 @@ impl Sheet {
 	/// Applies [Moment::transpose] on every item in `self`. see its
 	/// documentation for more info.
 	pub fn transpose(&mut self, shift: i8, transpose_ch9: bool) {
-		for m in &mut self.0 {
-			m.transpose(shift, transpose_ch9);
+		for (tick, m) in self.0.iter_mut().enumerate() {
+			self.1
+				.retain(tick as u32, m, |_, e| e.transpose(shift, transpose_ch9));
 		}
 	}
 
@@ -139,4 +163,10 @@ impl Sheet {
 	pub fn as_moments(&self) -> &[Moment] {
 		&self.0[..]
 	}
+
+	/// Returns a view of `self` that can be passed to a
+	/// [Player](crate::Player).
+	pub fn timeline(&self) -> Timeline<'_> {
+		self.into()
+	}
 }
@@ -1,96 +1,276 @@
-use std::collections::VecDeque;
+use std::{
+	collections::VecDeque,
+	ops::{Deref, DerefMut, Range},
+};
 
-use crate::{Event, Moment, Sheet};
+use crate::{Event, KeySignature, Moment, Sheet, SparseSheet, TimeSignature, Timeline};
 
-#[derive(Eq, PartialEq, Copy, Clone, Debug)]
-struct TimeSignature {
-	// Beats per bar.
-	numerator: u8,
-	// Note of a beat. A negative power of 2.
-	denominator: u8,
+/// A single measure, as yielded by [Bars].
+///
+/// A [Bar] dereferences to its moments, so it can be used like a
+/// `Vec<Moment>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Bar {
+	/// The position of this bar in the track, starting from 0.
+	pub index: usize,
+	/// The absolute position of the first tick of this bar.
+	pub start: u32,
+	/// The length of this bar in ticks; the same as `moments.len()`.
+	///
+	/// This can be shorter than the time signature implies, if the track ends
+	/// or the time signature changes before the bar is complete.
+	pub len: u32,
+	/// The time signature in effect at the start of this bar.
+	pub time_signature: TimeSignature,
+	/// The key signature in effect at the start of this bar.
+	pub key_signature: KeySignature,
+	/// The position of every beat in this bar, in ticks relative to `start`.
+	///
+	/// The first item is always `0`; beats past the end of a short bar are
+	/// not included.
+	pub beats: Vec<u32>,
+	/// Every tick in this bar.
+	pub moments: Vec<Moment>,
 }
 
-impl TimeSignature {
-	fn bar_32s(&self) -> f32 {
-		let note_as_32s = 2_f32.powi(5_i32 - self.denominator as i32);
-		// let note_as_32s = 32.0 / self.denominator as f32;
-		self.numerator as f32 * note_as_32s
+impl Bar {
+	/// Returns the absolute position of the tick right after this bar.
+	pub fn end(&self) -> u32 {
+		self.start + self.len
 	}
 }
 
-/// An iterator over bars in a MIDI [Sheet].
+impl Deref for Bar {
+	type Target = Vec<Moment>;
+	fn deref(&self) -> &Self::Target {
+		&self.moments
+	}
+}
+
+impl DerefMut for Bar {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.moments
+	}
+}
+
+// Finds the boundaries of bars, without needing the moments themselves.
+// The yielded bars have no moments.
 #[derive(Debug, Clone, PartialEq)]
-pub struct Bars {
+pub(crate) struct BarCursor {
 	time_sig: TimeSignature,
-	// beat_32s: u8,
-	tpb: f32,
-	buf: VecDeque<Moment>,
+	key_sig: KeySignature,
+	tpb: u16,
+	index: usize,
+	// Start of the next bar, in ticks.
+	tick: u32,
+	len: u32,
+	// Time signature changes, at most one per tick.
+	time_sigs: VecDeque<(u32, TimeSignature)>,
+	key_sigs: VecDeque<(u32, KeySignature)>,
 }
 
-impl Iterator for Bars {
-	type Item = Vec<Moment>;
+impl BarCursor {
+	pub(crate) fn new(timeline: Timeline<'_>, ticks_per_beat: u16) -> Self {
+		let mut time_sigs = VecDeque::new();
+		let mut key_sigs = VecDeque::new();
+		for (tick, m) in timeline {
+			if let Some(ts) = m.iter().find_map(Event::as_time_signature) {
+				time_sigs.push_back((tick, ts));
+			}
+			key_sigs.extend(
+				m.iter()
+					.filter_map(Event::as_key_signature)
+					.map(|ks| (tick, ks)),
+			);
+		}
+
+		Self {
+			tpb: ticks_per_beat,
+			time_sig: TimeSignature::default(),
+			key_sig: KeySignature::default(),
+			index: 0,
+			tick: 0,
+			len: timeline.len(),
+			time_sigs,
+			key_sigs,
+		}
+	}
+}
+
+// Only the numerator and the denominator affect the bars.
+fn same_meter(a: &TimeSignature, b: &TimeSignature) -> bool {
+	a.numerator == b.numerator && a.denominator == b.denominator
+}
+
+impl Iterator for BarCursor {
+	type Item = Bar;
 
 	fn next(&mut self) -> Option<Self::Item> {
+		if self.tick >= self.len {
+			return None;
+		}
+		let start = self.tick;
+
 		// Check if start of the bar has time signature.
-		let first = self.buf.pop_front()?;
-		if let Some(time_sig) = find_time_sig(&first) {
-			self.time_sig = time_sig;
+		if let Some(&(tick, time_sig)) = self.time_sigs.front() {
+			if tick == start {
+				self.time_sig = time_sig;
+				self.time_sigs.pop_front();
+			}
 		}
-		if self.buf.is_empty() {
-			return Some(vec![first]);
+		while let Some(&(_, key_sig)) = self.key_sigs.front().filter(|(t, _)| *t <= start) {
+			self.key_sig = key_sig;
+			self.key_sigs.pop_front();
 		}
+		let time_signature = self.time_sig;
 
-		let len_32nd = self.tpb / 8.0;
-		let chunk_len = (self.time_sig.bar_32s() * len_32nd) as usize;
-		let mut temp = Vec::with_capacity(chunk_len);
-		temp.push(first);
-
-		for _ in 1..chunk_len {
-			if let Some(moment) = self.buf.pop_front() {
-				let time_sig = find_time_sig(&moment);
-				temp.push(moment);
-				if let Some(time_sig) = time_sig {
-					if time_sig != self.time_sig {
-						self.time_sig = time_sig;
-						break;
-					}
-				}
-			} else {
+		let chunk_len = time_signature.bar_ticks(self.tpb);
+		let mut end = (start + chunk_len.max(1)).min(self.len);
+
+		// The bar ends early if the time signature changes.
+		while let Some(&(tick, time_sig)) = self.time_sigs.front() {
+			if tick >= end {
+				break;
+			}
+			self.time_sigs.pop_front();
+			if !same_meter(&time_sig, &self.time_sig) {
+				self.time_sig = time_sig;
+				end = tick + 1;
 				break;
 			}
 		}
 
-		Some(temp)
+		let beat = time_signature.beat_ticks(self.tpb);
+		let beats = (0..time_signature.numerator.max(1))
+			.map(|i| (i as f64 * beat) as u32)
+			.take_while(|b| *b < end - start)
+			.collect();
+
+		let index = self.index;
+		self.index += 1;
+		self.tick = end;
+		Some(Bar {
+			index,
+			start,
+			len: end - start,
+			time_signature,
+			key_signature: self.key_sig,
+			beats,
+			moments: Vec::new(),
+		})
+	}
+}
+
+// Returns the span of the given bars in ticks, or None if there are no such bars.
+pub(crate) fn bar_range(
+	timeline: Timeline<'_>,
+	ticks_per_beat: u16,
+	bars: Range<usize>,
+) -> Option<Range<u32>> {
+	let mut cursor = BarCursor::new(timeline, ticks_per_beat)
+		.skip(bars.start)
+		.take(bars.len());
+	let first = cursor.next()?;
+	let end = cursor.last().map_or(first.end(), |bar| bar.end());
+	Some(first.start..end)
+}
+
+/// An iterator over bars in a MIDI [Sheet].
+///
+/// This `struct` is created by [Sheet::into_bars] and
+/// [SparseSheet::into_bars].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bars {
+	cursor: BarCursor,
+	buf: VecDeque<(u32, Moment)>,
+}
+
+impl Iterator for Bars {
+	type Item = Bar;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let mut bar = self.cursor.next()?;
+		bar.moments.reserve(bar.len as usize);
+
+		for tick in bar.start..bar.end() {
+			match self.buf.front() {
+				Some((t, _)) if *t == tick => bar.moments.push(self.buf.pop_front().unwrap().1),
+				_ => bar.moments.push(Moment::default()),
+			}
+		}
+
+		Some(bar)
 	}
 }
 
 impl Sheet {
 	/// Returns an iterator that yields measures (bars) from this sheet.
 	///
+	/// The time signature is assumed to be 4/4 until the first time signature
+	/// event.
+	///
+	/// # Arguments
+	/// - `ticks_per_beat`: Obtained from a [Header](midly::Header), same value
+	///   used for constructing a [Ticker](crate::timers::Ticker).
+	pub fn into_bars(self, ticks_per_beat: u16) -> Bars {
+		SparseSheet::from(self).into_bars(ticks_per_beat)
+	}
+}
+
+impl SparseSheet {
+	/// Returns an iterator that yields measures (bars) from this sheet.
+	///
+	/// The yielded bars are the same as the ones [Sheet::into_bars] would
+	/// yield for the equivalent [Sheet].
+	///
 	/// # Arguments
 	/// - `ticks_per_beat`: Obtained from a [Header](midly::Header), same value
 	///   used for constructing a [Ticker](crate::timers::Ticker).
 	pub fn into_bars(self, ticks_per_beat: u16) -> Bars {
 		Bars {
-			tpb: ticks_per_beat as f32,
-			time_sig: TimeSignature {
-				numerator: 4,
-				denominator: 4,
-			},
-			// beat_32s: 24,
-			buf: self.0.into(),
+			cursor: BarCursor::new(self.timeline(), ticks_per_beat),
+			buf: self.moments.into(),
 		}
 	}
 }
 
-fn find_time_sig(m: &Moment) -> Option<TimeSignature> {
-	for e in &m.events {
-		if let Event::TimeSignature(n, d, ..) = e {
-			return Some(TimeSignature {
-				numerator: *n,
-				denominator: *d,
-			});
-		}
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn bars() {
+		let mut sheet = Sheet::new();
+		sheet.extend((0..40).map(|_| Moment::default()));
+		sheet[0].push(Event::KeySignature(2, false));
+		// 3/8 from the second bar on.
+		sheet[16].push(Event::TimeSignature(3, 3, 12, 8));
+		sheet[17].push(Event::KeySignature(-1, true));
+
+		let bars = sheet.into_bars(4).collect::<Vec<_>>();
+		let spans = bars
+			.iter()
+			.map(|b| (b.index, b.start, b.len))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			spans,
+			vec![(0, 0, 16), (1, 16, 6), (2, 22, 6), (3, 28, 6), (4, 34, 6)]
+		);
+		assert!(bars.iter().all(|b| b.moments.len() == b.len as usize));
+
+		assert_eq!(bars[0].time_signature, TimeSignature::default());
+		assert_eq!(bars[0].beats, vec![0, 4, 8, 12]);
+		assert_eq!(bars[0].key_signature.sharps, 2);
+		assert_eq!(bars[1].time_signature.numerator, 3);
+		assert_eq!(bars[1].beats, vec![0, 2, 4]);
+		assert_eq!(bars[1].key_signature.sharps, 2);
+		assert_eq!(
+			bars[2].key_signature,
+			KeySignature {
+				sharps: -1,
+				minor: true
+			}
+		);
+		assert_eq!(bars[1][0].len(), 1);
 	}
-	None
 }
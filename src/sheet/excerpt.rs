@@ -0,0 +1,319 @@
+use std::{
+	collections::{BTreeMap, VecDeque},
+	mem,
+	ops::Range,
+};
+
+use midly::{
+	num::{u4, u7},
+	MidiMessage,
+};
+
+use super::{bar::bar_range, Tracks};
+use crate::{ChaseState, Event, MidiEvent, Moment, Sheet, SparseSheet, Timeline};
+
+/// What to do with the notes that straddle the boundaries of an excerpt.
+///
+/// See [Sheet::excerpt].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Straddle {
+	/// Keep the notes, cut to the excerpt: notes sounding at the start are
+	/// played again at the first tick and notes sounding at the end are stopped
+	/// on the last tick with a synthetic `NoteOff`.
+	Trim,
+	/// Drop the notes that start before the excerpt or end after it.
+	Exclude,
+}
+
+impl Sheet {
+	/// Returns the ticks in `range` as a new, self-contained [Sheet].
+	///
+	/// The state of the track at `range.start` (tempo, time and key
+	/// signatures, SysEx messages, programs, controllers and so on; see
+	/// [ChaseState]) is recreated at the first tick of the excerpt so it plays
+	/// correctly on its own. Notes that straddle the boundaries are handled
+	/// according to `straddle`.
+	///
+	/// `range.end` is clamped to the length of `self`.
+	pub fn excerpt(&self, range: Range<u32>, straddle: Straddle) -> Self {
+		excerpt(self.into(), range, straddle).into()
+	}
+
+	/// Returns the given bars as a new, self-contained [Sheet].
+	///
+	/// Bars are counted from 0 and are the same ones [Sheet::into_bars] would
+	/// yield. See [Sheet::excerpt] for the details.
+	///
+	/// # Examples
+	/// ```no_run
+	/// use nodi::{Sheet, Straddle};
+	///
+	/// # let sheet = Sheet::new();
+	/// # let ticks_per_beat = 480;
+	/// // Bars 17 to 32, as counted by musicians.
+	/// let clip = sheet.excerpt_bars(ticks_per_beat, 16..32, Straddle::Trim);
+	/// ```
+	pub fn excerpt_bars(
+		&self,
+		ticks_per_beat: u16,
+		bars: Range<usize>,
+		straddle: Straddle,
+	) -> Self {
+		excerpt_bars(self.into(), ticks_per_beat, bars, straddle).into()
+	}
+}
+
+impl SparseSheet {
+	/// Returns the ticks in `range` as a new, self-contained [SparseSheet].
+	///
+	/// See [Sheet::excerpt].
+	pub fn excerpt(&self, range: Range<u32>, straddle: Straddle) -> Self {
+		excerpt(self.into(), range, straddle)
+	}
+
+	/// Returns the given bars as a new, self-contained [SparseSheet].
+	///
+	/// See [Sheet::excerpt_bars].
+	pub fn excerpt_bars(
+		&self,
+		ticks_per_beat: u16,
+		bars: Range<usize>,
+		straddle: Straddle,
+	) -> Self {
+		excerpt_bars(self.into(), ticks_per_beat, bars, straddle)
+	}
+}
+
+fn excerpt_bars(
+	timeline: Timeline<'_>,
+	ticks_per_beat: u16,
+	bars: Range<usize>,
+	straddle: Straddle,
+) -> SparseSheet {
+	match bar_range(timeline, ticks_per_beat, bars) {
+		Some(range) => excerpt(timeline, range, straddle),
+		None => SparseSheet::default(),
+	}
+}
+
+// A note event in the excerpt, with its position relative to the excerpt.
+struct Staged {
+	tick: u32,
+	track: u16,
+	event: Event,
+	keep: bool,
+}
+
+// Returns the channel, key and velocity of a note event; the velocity is `None` for note offs.
+fn note(e: &Event) -> Option<(u4, u7, Option<u7>)> {
+	match *e {
+		Event::Midi(MidiEvent {
+			channel,
+			message: MidiMessage::NoteOn { key, vel },
+		}) if vel > 0 => Some((channel, key, Some(vel))),
+		Event::Midi(MidiEvent {
+			channel,
+			message: MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. },
+		}) => Some((channel, key, None)),
+		_ => None,
+	}
+}
+
+fn excerpt(timeline: Timeline<'_>, range: Range<u32>, straddle: Straddle) -> SparseSheet {
+	let start = range.start;
+	let end = range.end.min(timeline.len());
+	if start >= end {
+		return SparseSheet::default();
+	}
+
+	let tracks = timeline.tracks();
+	let mut moments = timeline.iter().take_while(|(t, _)| *t < end).peekable();
+	let mut state = ChaseState::new();
+	// Notes sounding at `start`, oldest first: (velocity, track).
+	let mut before: BTreeMap<(u4, u7), VecDeque<(u7, u16)>> = BTreeMap::new();
+
+	while let Some((tick, moment)) = moments.next_if(|(t, _)| *t < start) {
+		for (track, e) in tracks.iter(tick, moment) {
+			state.update(e);
+			match note(e) {
+				Some((channel, key, Some(vel))) => before
+					.entry((channel, key))
+					.or_default()
+					.push_back((vel, track)),
+				Some((channel, key, None)) => {
+					before.entry((channel, key)).or_default().pop_front();
+				}
+				None => (),
+			}
+		}
+	}
+
+	let mut staged = state
+		.events()
+		.into_iter()
+		.map(|event| Staged {
+			tick: 0,
+			track: 0,
+			event,
+			keep: true,
+		})
+		.collect::<Vec<_>>();
+	// Notes started in the excerpt and not yet stopped, oldest first: indices into `staged`.
+	let mut sounding: BTreeMap<(u4, u7), VecDeque<usize>> = BTreeMap::new();
+
+	if straddle == Straddle::Trim {
+		for ((channel, key), notes) in mem::take(&mut before) {
+			for (vel, track) in notes {
+				sounding
+					.entry((channel, key))
+					.or_default()
+					.push_back(staged.len());
+				staged.push(Staged {
+					tick: 0,
+					track,
+					event: Event::Midi(MidiEvent {
+						channel,
+						message: MidiMessage::NoteOn { key, vel },
+					}),
+					keep: true,
+				});
+			}
+		}
+	}
+
+	for (tick, moment) in moments {
+		for (track, e) in tracks.iter(tick, moment) {
+			let keep = match note(e) {
+				Some((channel, key, Some(_))) => {
+					sounding
+						.entry((channel, key))
+						.or_default()
+						.push_back(staged.len());
+					true
+				}
+				// A note started before the excerpt, which is being excluded.
+				Some((channel, key, None))
+					if before
+						.get_mut(&(channel, key))
+						.and_then(|v| v.pop_front())
+						.is_some() =>
+				{
+					false
+				}
+				Some((channel, key, None)) => {
+					sounding
+						.get_mut(&(channel, key))
+						.and_then(|v| v.pop_front());
+					true
+				}
+				None => true,
+			};
+			staged.push(Staged {
+				tick: tick - start,
+				track,
+				event: e.clone(),
+				keep,
+			});
+		}
+	}
+
+	// Handle the notes still sounding at the end.
+	let mut open = sounding.into_values().flatten().collect::<Vec<_>>();
+	open.sort_unstable();
+	for i in open {
+		match straddle {
+			Straddle::Exclude => staged[i].keep = false,
+			Straddle::Trim => {
+				if let Some((channel, key, _)) = note(&staged[i].event) {
+					let off = Staged {
+						tick: end - start - 1,
+						track: staged[i].track,
+						event: Event::Midi(MidiEvent {
+							channel,
+							message: MidiMessage::NoteOff {
+								key,
+								vel: 64.into(),
+							},
+						}),
+						keep: true,
+					};
+					staged.push(off);
+				}
+			}
+		}
+	}
+
+	let mut moments: Vec<(u32, Moment)> = Vec::new();
+	let mut origins: Vec<(u32, Vec<u16>)> = Vec::new();
+	for s in staged.into_iter().filter(|s| s.keep) {
+		match (moments.last_mut(), origins.last_mut()) {
+			(Some((t, m)), Some((_, o))) if *t == s.tick => {
+				m.push(s.event);
+				o.push(s.track);
+			}
+			_ => {
+				moments.push((s.tick, vec![s.event].into()));
+				origins.push((s.tick, vec![s.track]));
+			}
+		}
+	}
+
+	SparseSheet {
+		moments,
+		len: end - start,
+		tracks: Tracks::new(tracks.count(), origins),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_support;
+
+	fn midi(message: MidiMessage) -> Event {
+		Event::Midi(test_support::midi(0, message))
+	}
+
+	fn on(key: u8) -> Event {
+		Event::Midi(test_support::on(0, key, 100))
+	}
+
+	fn off(key: u8) -> Event {
+		Event::Midi(test_support::off(0, key))
+	}
+
+	#[test]
+	fn excerpt() {
+		let program = midi(MidiMessage::ProgramChange { program: 5.into() });
+		let mut sheet = Sheet::new();
+		sheet.extend((0..10).map(|_| Moment::default()));
+		sheet[0].extend([Event::Tempo(400_000), program.clone(), on(60)]);
+		sheet[1].push(on(62));
+		sheet[3].extend([off(60), on(64)]);
+		sheet[5].push(off(62));
+		sheet[6].push(on(65));
+		sheet[7].push(off(64));
+		sheet[9].push(off(65));
+
+		let trimmed = sheet.excerpt(2..8, Straddle::Trim);
+		assert_eq!(trimmed.len(), 6);
+		assert_eq!(
+			trimmed[0].events,
+			vec![Event::Tempo(400_000), program.clone(), on(60), on(62)]
+		);
+		assert_eq!(trimmed[1].events, vec![off(60), on(64)]);
+		assert_eq!(trimmed[3].events, vec![off(62)]);
+		assert_eq!(trimmed[5].events, vec![off(64), off(65)]);
+
+		let excluded = SparseSheet::from(sheet).excerpt(2..8, Straddle::Exclude);
+		assert_eq!(excluded.len(), 6);
+		assert_eq!(
+			excluded.moments(),
+			&[
+				(0, vec![Event::Tempo(400_000), program].into()),
+				(1, vec![on(64)].into()),
+				(5, vec![off(64)].into()),
+			]
+		);
+	}
+}
@@ -0,0 +1,193 @@
+use midly::{Format, Header, MetaMessage, Smf, Timing, Track, TrackEvent, TrackEventKind};
+
+use crate::{Event, Sheet, SparseSheet, Timeline};
+
+impl Sheet {
+	/// Converts `self` into a Standard MIDI File, that can then be written
+	/// with [Smf::write_std] or [Smf::save].
+	///
+	/// # Arguments
+	/// - `format`: The format of the resulting file.
+	///   - [Format::SingleTrack] and [Format::Sequential] produce a single
+	///     track containing every event.
+	///   - [Format::Parallel] recreates the tracks `self` was created from, see
+	///     [Sheet::track_count] and [Sheet::iter_tracked]. If there is
+	///     only one such track, a "conductor" track containing every
+	///     non-channel event (tempo, time and key signatures, SysEx...) is
+	///     produced instead, followed by a track for every MIDI channel used in
+	///     `self`.
+	/// - `timing`: The timing of the resulting file. Usually the same
+	///   [Timing] the [Sheet] was created with.
+	///
+	/// # Examples
+	/// ```no_run
+	/// use midly::{Format, Smf};
+	/// use nodi::Sheet;
+	///
+	/// let data = std::fs::read("song.mid")?;
+	/// let Smf { header, tracks } = Smf::parse(&data)?;
+	/// let mut sheet = Sheet::parallel(&tracks);
+	/// sheet.transpose(2, false);
+	///
+	/// let smf = sheet.to_smf(Format::Parallel, header.timing);
+	/// smf.save("song-transposed.mid")?;
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn to_smf(&self, format: Format, timing: Timing) -> Smf<'_> {
+		timeline_to_smf(self.into(), format, timing)
+	}
+}
+
+impl SparseSheet {
+	/// Converts `self` into a Standard MIDI File.
+	///
+	/// See [Sheet::to_smf] for more.
+	pub fn to_smf(&self, format: Format, timing: Timing) -> Smf<'_> {
+		timeline_to_smf(self.into(), format, timing)
+	}
+}
+
+// Builds a track out of events with absolute positions.
+struct TrackBuilder<'a> {
+	events: Track<'a>,
+	last_tick: u32,
+}
+
+impl<'a> TrackBuilder<'a> {
+	fn new() -> Self {
+		Self {
+			events: Vec::new(),
+			last_tick: 0,
+		}
+	}
+
+	fn push(&mut self, tick: u32, kind: TrackEventKind<'a>) {
+		self.events.push(TrackEvent {
+			delta: (tick - self.last_tick).into(),
+			kind,
+		});
+		self.last_tick = tick;
+	}
+
+	// Ends the track at `end`, the position of the last tick.
+	fn finish(mut self, end: u32) -> Track<'a> {
+		self.push(
+			end.max(self.last_tick),
+			TrackEventKind::Meta(MetaMessage::EndOfTrack),
+		);
+		self.events
+	}
+}
+
+fn timeline_to_smf(timeline: Timeline<'_>, format: Format, timing: Timing) -> Smf<'_> {
+	let end = timeline.len().saturating_sub(1);
+	let origins = timeline.tracks();
+	let track_count = origins.count();
+
+	let tracks = match format {
+		Format::SingleTrack | Format::Sequential => {
+			let mut track = TrackBuilder::new();
+			for (tick, moment) in timeline {
+				for e in &moment.events {
+					track.push(tick, e.as_track_event());
+				}
+			}
+			vec![track.finish(end)]
+		}
+		Format::Parallel if track_count > 1 => {
+			let mut tracks: Vec<_> = (0..track_count).map(|_| TrackBuilder::new()).collect();
+
+			for (tick, moment) in timeline {
+				for (track, e) in origins.iter(tick, moment) {
+					tracks[track as usize].push(tick, e.as_track_event());
+				}
+			}
+
+			tracks.into_iter().map(|t| t.finish(end)).collect()
+		}
+		Format::Parallel => {
+			let mut conductor = TrackBuilder::new();
+			let mut channels: [Option<TrackBuilder<'_>>; 16] = Default::default();
+
+			for (tick, moment) in timeline {
+				for e in &moment.events {
+					match e {
+						Event::Midi(msg) => channels[msg.channel.as_int() as usize]
+							.get_or_insert_with(TrackBuilder::new)
+							.push(tick, e.as_track_event()),
+						_ => conductor.push(tick, e.as_track_event()),
+					}
+				}
+			}
+
+			std::iter::once(conductor.finish(end))
+				.chain(channels.into_iter().flatten().map(|t| t.finish(end)))
+				.collect()
+		}
+	};
+
+	Smf {
+		header: Header::new(format, timing),
+		tracks,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use midly::{num::u4, MidiMessage};
+
+	use super::*;
+	use crate::{MidiEvent, Moment, TextKind};
+
+	#[test]
+	fn round_trip() {
+		let note = |channel: u8, key: u8| {
+			Event::Midi(MidiEvent {
+				channel: u4::new(channel),
+				message: MidiMessage::NoteOn {
+					key: key.into(),
+					vel: 100.into(),
+				},
+			})
+		};
+
+		let mut sheet = Sheet::new();
+		sheet.push(Moment {
+			events: vec![Event::Tempo(500_000), Event::TimeSignature(3, 2, 24, 8)],
+		});
+		sheet.push(Moment {
+			events: vec![
+				Event::SysEx(vec![0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7]),
+				Event::Text(TextKind::Lyric, b"la".to_vec()),
+				note(0, 60),
+			],
+		});
+		sheet.extend((0..100).map(|_| Moment::default()));
+		sheet.push(Moment {
+			events: vec![Event::KeySignature(-2, true), note(3, 50)],
+		});
+		// An escape starting with 0xF0 is not a SysEx message.
+		sheet.push(Moment {
+			events: vec![Event::Escape(vec![0xF0, 0xF7])],
+		});
+		sheet.extend((0..20).map(|_| Moment::default()));
+
+		let timing = Timing::Metrical(480.into());
+		let mut data = Vec::new();
+		sheet
+			.to_smf(Format::SingleTrack, timing)
+			.write_std(&mut data)
+			.unwrap();
+		let smf = Smf::parse(&data).unwrap();
+		assert_eq!(smf.header, Header::new(Format::SingleTrack, timing));
+		assert_eq!(Sheet::single(&smf.tracks[0]), sheet);
+
+		let smf = sheet.to_smf(Format::Parallel, timing);
+		assert_eq!(smf.tracks.len(), 3);
+		let parallel = Sheet::parallel(&smf.tracks);
+		assert_eq!(parallel.track_count(), 3);
+		// Exporting again keeps the same tracks.
+		assert_eq!(parallel.to_smf(Format::Parallel, timing), smf);
+		assert_eq!(parallel, sheet);
+	}
+}
@@ -1,12 +1,15 @@
 use core::{
 	borrow::Borrow,
 	convert::TryFrom,
+	hash::{Hash, Hasher},
 	iter::{FromIterator, IntoIterator},
+	mem,
 	ops::{Deref, Index, IndexMut},
 };
 
 use midly::TrackEvent;
 
+use super::Tracks;
 use crate::{Event, Moment, Sheet};
 
 impl Extend<Moment> for Sheet {
@@ -42,7 +45,22 @@ impl From<&[TrackEvent<'_>]> for Sheet {
 			}
 		}
 
-		Self(buf)
+		Self(buf, Tracks::default())
+	}
+}
+
+// The tracks the events originate from are not compared.
+impl PartialEq for Sheet {
+	fn eq(&self, other: &Self) -> bool {
+		self.0 == other.0
+	}
+}
+
+impl Eq for Sheet {}
+
+impl Hash for Sheet {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.0.hash(state);
 	}
 }
 
@@ -54,7 +72,7 @@ impl Borrow<[Moment]> for Sheet {
 
 impl FromIterator<Moment> for Sheet {
 	fn from_iter<I: IntoIterator<Item = Moment>>(it: I) -> Self {
-		Self(Vec::from_iter(it))
+		Self(Vec::from_iter(it), Tracks::default())
 	}
 }
 
@@ -80,6 +98,14 @@ where
 	Vec<Moment>: IndexMut<I>,
 {
 	fn index_mut(&mut self, i: I) -> &mut Self::Output {
-		self.0.index_mut(i)
+		let base = self.0.as_ptr() as usize;
+		let out = self.0.index_mut(i);
+		// The output is a moment or a slice of them; the tracks of the borrowed
+		// moments are forgotten, their events may be reordered or replaced.
+		let size = mem::size_of::<Moment>();
+		let start = (out as *mut Self::Output as *mut u8 as usize - base) / size;
+		let end = start + mem::size_of_val(out) / size;
+		self.1.forget(start as u32..end as u32);
+		out
 	}
 }
@@ -0,0 +1,165 @@
+use std::{fs, path::Path};
+
+use midly::{Format, Smf};
+
+use crate::{timers::AnyTimer, Error, Sheet, SparseSheet};
+
+impl Sheet {
+	/// Creates a [Sheet] out of a parsed MIDI file, along with a [Timer](crate::Timer) for
+	/// playing it.
+	///
+	/// The tracks are merged with [Sheet::parallel] if the file format is
+	/// [Format::Parallel], otherwise they're joined with [Sheet::sequential].
+	///
+	/// # Examples
+	/// ```no_run
+	/// use midly::Smf;
+	/// use nodi::Sheet;
+	///
+	/// let data = std::fs::read("song.mid")?;
+	/// let smf = Smf::parse(&data)?;
+	/// let (sheet, timer) = Sheet::from_smf(&smf);
+	/// # Ok::<(), Box<dyn std::error::Error>>(())
+	/// ```
+	pub fn from_smf(smf: &Smf<'_>) -> (Self, AnyTimer) {
+		let sheet = match smf.header.format {
+			// A file can specify the format as single and still have multiple tracks in it.
+			Format::SingleTrack | Format::Sequential => Self::sequential(&smf.tracks),
+			Format::Parallel => Self::parallel(&smf.tracks),
+		};
+		(sheet, smf.header.timing.into())
+	}
+
+	/// Parses a MIDI file from its bytes, then calls [Sheet::from_smf].
+	///
+	/// # Errors
+	/// Will return [Error::Parse] if the data is not a valid MIDI file.
+	pub fn from_bytes(data: &[u8]) -> Result<(Self, AnyTimer), Error> {
+		Ok(Self::from_smf(&Smf::parse(data)?))
+	}
+
+	/// Reads and parses a MIDI file, then calls [Sheet::from_smf].
+	///
+	/// # Errors
+	/// Will return [Error::Io] if the file could not be read, or
+	/// [Error::Parse] if it is not a valid MIDI file.
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<(Self, AnyTimer), Error> {
+		Self::from_bytes(&fs::read(path)?)
+	}
+}
+
+impl SparseSheet {
+	/// Creates a [SparseSheet] out of a parsed MIDI file, along with a
+	/// [Timer](crate::Timer) for playing it.
+	///
+	/// See [Sheet::from_smf].
+	pub fn from_smf(smf: &Smf<'_>) -> (Self, AnyTimer) {
+		let sheet = match smf.header.format {
+			Format::SingleTrack | Format::Sequential => Self::sequential(&smf.tracks),
+			Format::Parallel => Self::parallel(&smf.tracks),
+		};
+		(sheet, smf.header.timing.into())
+	}
+
+	/// Parses a MIDI file from its bytes, then calls [SparseSheet::from_smf].
+	///
+	/// # Errors
+	/// Will return [Error::Parse] if the data is not a valid MIDI file.
+	pub fn from_bytes(data: &[u8]) -> Result<(Self, AnyTimer), Error> {
+		Ok(Self::from_smf(&Smf::parse(data)?))
+	}
+
+	/// Reads and parses a MIDI file, then calls [SparseSheet::from_smf].
+	///
+	/// # Errors
+	/// Will return [Error::Io] if the file could not be read, or
+	/// [Error::Parse] if it is not a valid MIDI file.
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<(Self, AnyTimer), Error> {
+		Self::from_bytes(&fs::read(path)?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use midly::{Fps, Header, MetaMessage, MidiMessage, Timing, TrackEvent, TrackEventKind};
+
+	use super::*;
+	use crate::{
+		timers::{FixedTempo, Ticker},
+		Timer,
+	};
+
+	fn smf(format: Format, timing: Timing) -> Smf<'static> {
+		let event = |delta: u32, kind| TrackEvent {
+			delta: delta.into(),
+			kind,
+		};
+		let end = TrackEventKind::Meta(MetaMessage::EndOfTrack);
+		let note = TrackEventKind::Midi {
+			channel: 0.into(),
+			message: MidiMessage::NoteOn {
+				key: 60.into(),
+				vel: 100.into(),
+			},
+		};
+
+		Smf {
+			header: Header::new(format, timing),
+			tracks: vec![
+				vec![
+					event(0, TrackEventKind::Meta(MetaMessage::Tempo(480_000.into()))),
+					event(0, end),
+				],
+				vec![event(480, note), event(0, end)],
+			],
+		}
+	}
+
+	#[test]
+	fn metrical() {
+		let smf = smf(Format::Parallel, Timing::Metrical(480.into()));
+		let mut data = Vec::new();
+		smf.write_std(&mut data).unwrap();
+
+		let (sheet, mut timer) = Sheet::from_bytes(&data).unwrap();
+		assert_eq!(sheet, Sheet::parallel(&smf.tracks));
+		assert_eq!(timer, AnyTimer::Ticker(Ticker::new(480)));
+		// A millisecond per tick after the first.
+		assert_eq!(timer.duration(&sheet), Duration::from_millis(480));
+
+		let (sparse, _) = SparseSheet::from_bytes(&data).unwrap();
+		assert_eq!(sparse, SparseSheet::from(sheet));
+	}
+
+	#[test]
+	fn timecode() {
+		// 25 frames per second, 40 ticks per frame: a tick lasts a millisecond.
+		let smf = smf(Format::Sequential, Timing::Timecode(Fps::Fps25, 40));
+		let path = std::env::temp_dir().join(format!("nodi-load-{}.mid", std::process::id()));
+		smf.save(&path).unwrap();
+		let opened = Sheet::open(&path);
+		let sparse = SparseSheet::open(&path);
+		fs::remove_file(&path).unwrap();
+
+		let (sheet, mut timer) = opened.unwrap();
+		assert_eq!(sheet, Sheet::sequential(&smf.tracks));
+		assert_eq!(timer, AnyTimer::FixedTempo(FixedTempo(1000)));
+		// Tempo changes are ignored.
+		assert_eq!(timer.duration(&sheet), Duration::from_millis(482));
+		assert_eq!(sparse.unwrap().0, SparseSheet::from(sheet));
+	}
+
+	#[test]
+	fn errors() {
+		assert!(matches!(
+			Sheet::from_bytes(b"not a midi file"),
+			Err(Error::Parse(_))
+		));
+		assert!(matches!(
+			SparseSheet::open("/nonexistent/song.mid"),
+			Err(Error::Io(_))
+		));
+	}
+}
@@ -0,0 +1,344 @@
+use std::{
+	convert::TryFrom,
+	hash::{Hash, Hasher},
+	mem,
+};
+
+use midly::TrackEvent;
+
+use super::Tracks;
+use crate::{Event, Moment, Sheet, Timeline};
+
+/// A memory efficient alternative to [Sheet].
+///
+/// Where a [Sheet] stores one [Moment] for every MIDI tick, a [SparseSheet]
+/// only stores the non-empty ones, keyed by their absolute position in ticks.
+/// This makes a big difference for long files with a high ticks-per-beat
+/// value, where the vast majority of the ticks are silent.
+///
+/// A [SparseSheet] can be played by a [Player](crate::Player), measured by a
+/// [Timer](crate::Timer::sparse_duration) and split into [Bars](crate::Bars)
+/// just like a [Sheet]; you can also convert between the two with [From].
+#[derive(Default, Clone, Debug)]
+pub struct SparseSheet {
+	// Sorted by tick, never contains empty moments and every tick is less than `len`.
+	pub(crate) moments: Vec<(u32, Moment)>,
+	pub(crate) len: u32,
+	// Not compared, see Tracks.
+	pub(crate) tracks: Tracks,
+}
+
+impl PartialEq for SparseSheet {
+	fn eq(&self, other: &Self) -> bool {
+		self.moments == other.moments && self.len == other.len
+	}
+}
+
+impl Eq for SparseSheet {}
+
+impl Hash for SparseSheet {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.moments.hash(state);
+		self.len.hash(state);
+	}
+}
+
+impl SparseSheet {
+	/// Creates a [SparseSheet] from a slice of [TrackEvent]s.
+	///
+	/// # Notes
+	/// Use this when the MIDI file header specifies the format to be 0, meaning
+	/// `single`.
+	pub fn single(events: &[TrackEvent<'_>]) -> Self {
+		events.into()
+	}
+
+	/// Creates a [SparseSheet] from many tracks, merging all of them into one.
+	///
+	/// The index of the track every event originates from is recorded, see
+	/// [SparseSheet::iter_tracked] and [SparseSheet::into_tracks].
+	///
+	/// # Notes
+	/// Use this when a MIDI file header specifies the format to be of 1,
+	/// meaning parallel.
+	pub fn parallel(tracks: &[Vec<TrackEvent<'_>>]) -> Self {
+		let mut sheet = Self::default();
+		for (i, track) in tracks.iter().enumerate() {
+			sheet.merge_with(Self::from_track(i as u16, track));
+		}
+		sheet
+	}
+
+	/// Creates a [SparseSheet] from every track, appending them end to
+	/// end.
+	///
+	/// # Notes
+	/// Use this when a MIDI file header specifies the type as 2, meaning
+	/// `sequential`.
+	pub fn sequential(tracks: &[Vec<TrackEvent<'_>>]) -> Self {
+		let mut sheet = Self::default();
+		for (i, track) in tracks.iter().enumerate() {
+			sheet.append(Self::from_track(i as u16, track));
+		}
+		sheet
+	}
+
+	/// Creates a new, blank [SparseSheet].
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Returns how many MIDI ticks this [SparseSheet] spans.
+	///
+	/// This is the same value [Sheet::len] would return for the equivalent
+	/// [Sheet], not the number of stored [Moment]s.
+	pub fn len(&self) -> u32 {
+		self.len
+	}
+
+	/// Returns `Self::len() == 0`.
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// Returns a view of `self` that can be passed to a
+	/// [Player](crate::Player).
+	pub fn timeline(&self) -> Timeline<'_> {
+		self.into()
+	}
+
+	/// Returns the stored (non-empty) [Moment]s, paired with their absolute
+	/// position in ticks.
+	pub fn moments(&self) -> &[(u32, Moment)] {
+		&self.moments
+	}
+
+	/// Returns an iterator over every stored [Moment], paired with its
+	/// absolute position in ticks.
+	pub fn iter(&self) -> std::slice::Iter<'_, (u32, Moment)> {
+		self.moments.iter()
+	}
+
+	/// Returns the [Moment] at the given tick, if it is not empty.
+	pub fn get(&self, tick: u32) -> Option<&Moment> {
+		self.moments
+			.binary_search_by_key(&tick, |(t, _)| *t)
+			.ok()
+			.map(|i| &self.moments[i].1)
+	}
+
+	/// Adds an [Event] at the given tick, extending `self` if needed.
+	pub fn insert(&mut self, tick: u32, event: Event) {
+		match self.moments.binary_search_by_key(&tick, |(t, _)| *t) {
+			Ok(i) => {
+				let m = &mut self.moments[i].1;
+				m.push(event);
+				self.tracks.pushed(tick, m);
+			}
+			Err(i) => self.moments.insert(i, (tick, vec![event].into())),
+		}
+		self.len = self.len.max(tick + 1);
+	}
+
+	/// Adds a single [Moment] at the end of this sheet.
+	pub fn push(&mut self, m: Moment) {
+		if !m.is_empty() {
+			self.moments.push((self.len, m));
+		}
+		self.len += 1;
+	}
+
+	/// Merges `self` with another [SparseSheet], destroying the other.
+	///
+	/// # Notes
+	/// This method will combine every moment in both sheets into one. If you
+	/// want to join them end to end instead, use [SparseSheet::append].
+	pub fn merge_with(&mut self, mut other: Self) {
+		let mut tracks = mem::take(&mut self.tracks);
+		tracks.merge(
+			mem::take(&mut other.tracks),
+			|tick| self.get(tick).map_or(0, |m| m.len()),
+			|tick| other.get(tick).map_or(0, |m| m.len()),
+		);
+		self.tracks = tracks;
+		self.len = self.len.max(other.len);
+		if other.moments.is_empty() {
+			return;
+		}
+		if self.moments.is_empty() {
+			self.moments = other.moments;
+			return;
+		}
+
+		let mine = mem::take(&mut self.moments);
+		let mut merged = Vec::with_capacity(mine.len() + other.moments.len());
+		let mut mine = mine.into_iter().peekable();
+		let mut theirs = other.moments.into_iter().peekable();
+
+		loop {
+			let next = match (mine.peek(), theirs.peek()) {
+				(Some((a, _)), Some((b, _))) if a == b => {
+					let (tick, mut m) = mine.next().unwrap();
+					m.extend(theirs.next().unwrap().1.events);
+					(tick, m)
+				}
+				(Some((a, _)), Some((b, _))) if a < b => mine.next().unwrap(),
+				(_, Some(_)) => theirs.next().unwrap(),
+				(Some(_), None) => mine.next().unwrap(),
+				(None, None) => break,
+			};
+			merged.push(next);
+		}
+
+		self.moments = merged;
+	}
+
+	/// Appends another [SparseSheet] to the end of `self`, destroying the
+	/// other.
+	pub fn append(&mut self, other: Self) {
+		let offset = self.len;
+		self.tracks.append(other.tracks, offset);
+		self.moments.extend(
+			other
+				.moments
+				.into_iter()
+				.map(|(tick, m)| (tick + offset, m)),
+		);
+		self.len += other.len;
+	}
+
+	/// Transposes every note in this sheet.
+	///
+	/// Applies [Moment::transpose] on every item in `self`. see its
+	/// documentation for more info.
+	pub fn transpose(&mut self, shift: i8, transpose_ch9: bool) {
+		for (tick, m) in &mut self.moments {
+			self.tracks
+				.retain(*tick, m, |_, e| e.transpose(shift, transpose_ch9));
+		}
+		self.moments.retain(|(_, m)| !m.is_empty());
+	}
+}
+
+impl From<&[TrackEvent<'_>]> for SparseSheet {
+	fn from(events: &[TrackEvent<'_>]) -> Self {
+		let mut moments: Vec<(u32, Moment)> = Vec::new();
+		let mut cur_pos = 0_u32;
+
+		for event in events {
+			cur_pos += u32::from(event.delta);
+			if let Ok(e) = Event::try_from(event.kind) {
+				match moments.last_mut() {
+					Some((tick, m)) if *tick == cur_pos => m.push(e),
+					_ => moments.push((cur_pos, vec![e].into())),
+				}
+			}
+		}
+
+		Self {
+			moments,
+			len: cur_pos + 1,
+			tracks: Tracks::default(),
+		}
+	}
+}
+
+impl From<Sheet> for SparseSheet {
+	fn from(sheet: Sheet) -> Self {
+		let len = sheet.len() as u32;
+		let moments = sheet
+			.0
+			.into_iter()
+			.enumerate()
+			.filter(|(_, m)| !m.is_empty())
+			.map(|(i, m)| (i as u32, m))
+			.collect();
+
+		Self {
+			moments,
+			len,
+			tracks: sheet.1,
+		}
+	}
+}
+
+impl From<SparseSheet> for Sheet {
+	fn from(sheet: SparseSheet) -> Self {
+		let mut buf = vec![Moment::default(); sheet.len as usize];
+		for (tick, m) in sheet.moments {
+			buf[tick as usize] = m;
+		}
+		Self(buf, sheet.tracks)
+	}
+}
+
+impl Extend<Moment> for SparseSheet {
+	fn extend<T: IntoIterator<Item = Moment>>(&mut self, moments: T) {
+		for m in moments {
+			self.push(m);
+		}
+	}
+}
+
+impl IntoIterator for SparseSheet {
+	type IntoIter = std::vec::IntoIter<Self::Item>;
+	type Item = (u32, Moment);
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.moments.into_iter()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use midly::{MetaMessage, MidiMessage, TrackEventKind};
+
+	use super::*;
+	use crate::{timers::Ticker, Timer};
+
+	fn track(events: &[(u32, u8)]) -> Vec<TrackEvent<'static>> {
+		let mut track: Vec<_> = events
+			.iter()
+			.map(|&(delta, key)| TrackEvent {
+				delta: delta.into(),
+				kind: TrackEventKind::Midi {
+					channel: 0.into(),
+					message: MidiMessage::NoteOn {
+						key: key.into(),
+						vel: 64.into(),
+					},
+				},
+			})
+			.collect();
+		track.push(TrackEvent {
+			delta: 10.into(),
+			kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+		});
+		track
+	}
+
+	#[test]
+	fn matches_dense() {
+		let tracks = vec![
+			track(&[(0, 60), (48, 62), (0, 64), (96, 65)]),
+			track(&[(48, 40), (200, 41)]),
+			track(&[]),
+		];
+
+		let dense = Sheet::parallel(&tracks);
+		let sparse = SparseSheet::parallel(&tracks);
+		assert_eq!(dense.len() as u32, sparse.len());
+		assert_eq!(Sheet::from(sparse.clone()), dense);
+		assert_eq!(SparseSheet::from(dense.clone()), sparse);
+
+		let ticker = Ticker::with_initial_tempo(48, 480_000);
+		assert_eq!(
+			{ ticker }.sparse_duration(&sparse),
+			{ ticker }.duration(&dense)
+		);
+
+		let dense = Sheet::sequential(&tracks);
+		let sparse = SparseSheet::sequential(&tracks);
+		assert_eq!(Sheet::from(sparse), dense);
+	}
+}
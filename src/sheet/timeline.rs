@@ -0,0 +1,110 @@
+use std::{iter::Enumerate, slice};
+
+use super::tracks::{Tracks, NO_TRACKS};
+use crate::{Moment, Sheet, SparseSheet};
+
+/// A borrowed view over the [Moment]s of a [Sheet] or a [SparseSheet].
+///
+/// This is what [Player::play](crate::Player::play) consumes; you rarely need
+/// to construct one by hand since `&Sheet`, `&SparseSheet` and `&[Moment]`
+/// all convert into it.
+#[derive(Copy, Clone, Debug)]
+pub struct Timeline<'a>(Inner<'a>, &'a Tracks);
+
+#[derive(Copy, Clone, Debug)]
+enum Inner<'a> {
+	Dense(&'a [Moment]),
+	Sparse {
+		moments: &'a [(u32, Moment)],
+		len: u32,
+	},
+}
+
+impl<'a> Timeline<'a> {
+	/// Returns the length of the timeline, in MIDI ticks.
+	pub fn len(&self) -> u32 {
+		match self.0 {
+			Inner::Dense(moments) => moments.len() as u32,
+			Inner::Sparse { len, .. } => len,
+		}
+	}
+
+	/// Returns `self.len() == 0`.
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	// Returns the tracks the events originate from.
+	pub(crate) fn tracks(&self) -> &'a Tracks {
+		self.1
+	}
+
+	/// Returns an iterator over every non-empty [Moment], paired with its
+	/// absolute position in ticks.
+	pub fn iter(&self) -> TimelineIter<'a> {
+		TimelineIter(match self.0 {
+			Inner::Dense(moments) => IterInner::Dense(moments.iter().enumerate()),
+			Inner::Sparse { moments, .. } => IterInner::Sparse(moments.iter()),
+		})
+	}
+}
+
+impl<'a> From<&'a [Moment]> for Timeline<'a> {
+	fn from(moments: &'a [Moment]) -> Self {
+		Self(Inner::Dense(moments), &NO_TRACKS)
+	}
+}
+
+impl<'a> From<&'a Vec<Moment>> for Timeline<'a> {
+	fn from(moments: &'a Vec<Moment>) -> Self {
+		Self(Inner::Dense(moments), &NO_TRACKS)
+	}
+}
+
+impl<'a> From<&'a Sheet> for Timeline<'a> {
+	fn from(sheet: &'a Sheet) -> Self {
+		Self(Inner::Dense(&sheet.0), &sheet.1)
+	}
+}
+
+impl<'a> From<&'a SparseSheet> for Timeline<'a> {
+	fn from(sheet: &'a SparseSheet) -> Self {
+		Self(
+			Inner::Sparse {
+				moments: &sheet.moments,
+				len: sheet.len,
+			},
+			&sheet.tracks,
+		)
+	}
+}
+
+impl<'a> IntoIterator for Timeline<'a> {
+	type IntoIter = TimelineIter<'a>;
+	type Item = (u32, &'a Moment);
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.iter()
+	}
+}
+
+/// An iterator over the non-empty [Moment]s of a [Timeline].
+#[derive(Clone, Debug)]
+pub struct TimelineIter<'a>(IterInner<'a>);
+
+#[derive(Clone, Debug)]
+enum IterInner<'a> {
+	Dense(Enumerate<slice::Iter<'a, Moment>>),
+	Sparse(slice::Iter<'a, (u32, Moment)>),
+}
+
+impl<'a> Iterator for TimelineIter<'a> {
+	type Item = (u32, &'a Moment);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		match &mut self.0 {
+			IterInner::Dense(it) => it.find(|(_, m)| !m.is_empty()).map(|(i, m)| (i as u32, m)),
+			IterInner::Sparse(it) => it.next().map(|(tick, m)| (*tick, m)),
+		}
+	}
+}
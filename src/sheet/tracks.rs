@@ -0,0 +1,420 @@
+use std::{mem, ops::Range};
+
+use midly::TrackEvent;
+
+use crate::{Event, Moment, Sheet, SparseSheet, TextKind, Timeline};
+
+// The index of the track every event of a sheet originates from.
+//
+// This is kept next to the moments rather than in them, so that it takes no
+// part in comparisons. Only the moments with an event from a track other than 0
+// are listed, sorted by tick, along with the track of each of their events.
+// The entry of a moment borrowed mutably by the user is dropped, since its events
+// may be reordered or replaced: they then belong to the track 0. An entry that
+// no longer matches the length of its moment is ignored as well.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Tracks {
+	// The number of tracks the sheet was created from.
+	count: u16,
+	moments: Vec<(u32, Vec<u16>)>,
+}
+
+pub(crate) static NO_TRACKS: Tracks = Tracks {
+	count: 0,
+	moments: Vec::new(),
+};
+
+impl Tracks {
+	// Records every event of `timeline` as originating from `index`.
+	fn single(index: u16, timeline: Timeline<'_>) -> Self {
+		let moments = match index {
+			0 => Vec::new(),
+			_ => timeline
+				.iter()
+				.map(|(tick, m)| (tick, vec![index; m.len()]))
+				.collect(),
+		};
+		Self {
+			count: index + 1,
+			moments,
+		}
+	}
+
+	// Creates a table out of the track of every event of the moments at the given ticks.
+	pub(crate) fn new(count: u16, mut moments: Vec<(u32, Vec<u16>)>) -> Self {
+		moments.retain(|(_, tracks)| tracks.iter().any(|&t| t != 0));
+		Self { count, moments }
+	}
+
+	pub(crate) fn count(&self) -> u16 {
+		self.count.max(1)
+	}
+
+	// Returns the position of the entry of `m`, the moment at `tick`, if it is in sync.
+	fn find(&self, tick: u32, m: &Moment) -> Option<usize> {
+		self.moments
+			.binary_search_by_key(&tick, |(t, _)| *t)
+			.ok()
+			.filter(|&i| self.moments[i].1.len() == m.len())
+	}
+
+	// Returns the events of `m`, the moment at `tick`, paired with their track.
+	pub(crate) fn iter<'a>(
+		&'a self,
+		tick: u32,
+		m: &'a Moment,
+	) -> impl Iterator<Item = (u16, &'a Event)> + 'a {
+		let tracks = self.find(tick, m).map(|i| self.moments[i].1.as_slice());
+		m.iter()
+			.enumerate()
+			.map(move |(i, e)| (tracks.map_or(0, |t| t[i]), e))
+	}
+
+	// Keeps the events of `m`, the moment at `tick`, for which `f` returns true.
+	pub(crate) fn retain<F>(&mut self, tick: u32, m: &mut Moment, mut f: F)
+	where
+		F: FnMut(u16, &mut Event) -> bool,
+	{
+		let i = match self.moments.binary_search_by_key(&tick, |(t, _)| *t) {
+			Ok(i) if self.moments[i].1.len() == m.len() => i,
+			Ok(i) => {
+				self.moments.remove(i);
+				return m.retain_mut(|e| f(0, e));
+			}
+			Err(_) => return m.retain_mut(|e| f(0, e)),
+		};
+
+		let mut tracks = mem::take(&mut self.moments[i].1).into_iter();
+		let mut kept = Vec::with_capacity(m.len());
+		m.retain_mut(|e| {
+			let track = tracks.next().unwrap();
+			let keep = f(track, e);
+			if keep {
+				kept.push(track);
+			}
+			keep
+		});
+		self.moments[i].1 = kept;
+	}
+
+	// Forgets the tracks of the moments at `ticks`, which the caller may modify.
+	pub(crate) fn forget(&mut self, ticks: Range<u32>) {
+		let start = self.moments.partition_point(|(t, _)| *t < ticks.start);
+		let end = self.moments.partition_point(|(t, _)| *t < ticks.end);
+		self.moments.drain(start..end);
+	}
+
+	// Records that an event from the track 0 was added to the end of `m`, the moment at `tick`.
+	pub(crate) fn pushed(&mut self, tick: u32, m: &Moment) {
+		if let Ok(i) = self.moments.binary_search_by_key(&tick, |(t, _)| *t) {
+			let tracks = &mut self.moments[i].1;
+			if tracks.len() + 1 == m.len() {
+				tracks.push(0);
+			}
+		}
+	}
+
+	// Merges `other` into `self`, as the moments of their sheets are merged: the
+	// events of `other` come after the ones of `self`. `len` and `other_len`
+	// return the number of events at a tick before the merge.
+	pub(crate) fn merge<F, G>(&mut self, other: Self, len: F, other_len: G)
+	where
+		F: Fn(u32) -> usize,
+		G: Fn(u32) -> usize,
+	{
+		self.count = self.count.max(other.count);
+		if other.moments.is_empty() {
+			return;
+		}
+
+		let mut mine = mem::take(&mut self.moments).into_iter().peekable();
+		let mut theirs = other.moments.into_iter().peekable();
+		loop {
+			let tick = match (mine.peek(), theirs.peek()) {
+				(Some((a, _)), Some((b, _))) => *a.min(b),
+				(Some((a, _)), None) => *a,
+				(None, Some((b, _))) => *b,
+				(None, None) => break,
+			};
+			let (len, other_len) = (len(tick), other_len(tick));
+			let mut tracks = match mine.next_if(|(t, _)| *t == tick) {
+				Some((_, v)) if v.len() == len => v,
+				_ => vec![0; len],
+			};
+			match theirs.next_if(|(t, _)| *t == tick) {
+				Some((_, v)) if v.len() == other_len => tracks.extend(v),
+				_ => tracks.resize(len + other_len, 0),
+			}
+			self.moments.push((tick, tracks));
+		}
+	}
+
+	// Appends `other` to `self`, as the moments of its sheet are appended at `offset`.
+	pub(crate) fn append(&mut self, other: Self, offset: u32) {
+		self.count = self.count.max(other.count);
+		self.moments.extend(
+			other
+				.moments
+				.into_iter()
+				.map(|(tick, tracks)| (tick + offset, tracks)),
+		);
+	}
+}
+
+impl Sheet {
+	// Creates a Sheet out of a single track, recording its index as the origin of every event.
+	pub(crate) fn from_track(index: u16, events: &[TrackEvent<'_>]) -> Self {
+		let mut sheet = Self::from(events);
+		sheet.1 = Tracks::single(index, (&sheet).into());
+		sheet
+	}
+
+	/// Returns the number of tracks `self` was created from, including the
+	/// ones without any event.
+	///
+	/// A [Sheet] that was not created from several tracks, for example with
+	/// [Sheet::single] or by hand, counts as a single track.
+	pub fn track_count(&self) -> u16 {
+		self.1.count()
+	}
+
+	/// Returns the events at `tick`, paired with the index of the track they
+	/// originate from.
+	///
+	/// # Notes
+	/// The methods of [Sheet] keep the tracks up to date, but a [Moment]
+	/// borrowed mutably, by indexing or with [Sheet::iter_mut], loses them:
+	/// its events are considered to originate from the track `0`.
+	pub fn iter_tracked(&self, tick: u32) -> impl Iterator<Item = (u16, &Event)> + '_ {
+		self.0
+			.get(tick as usize)
+			.into_iter()
+			.flat_map(move |m| self.1.iter(tick, m))
+	}
+
+	/// Returns the name of the given track, if it has one.
+	///
+	/// This is the text of the first [TextKind::TrackName] event originating
+	/// from `track`.
+	pub fn track_name(&self, track: u16) -> Option<&[u8]> {
+		track_name(self.into(), track)
+	}
+
+	/// Keeps only the events originating from the tracks for which `f` returns
+	/// `true`.
+	///
+	/// # Examples
+	/// ```no_run
+	/// # let mut sheet = nodi::Sheet::new();
+	/// // Mute the track 2.
+	/// sheet.retain_tracks(|track| track != 2);
+	/// // Solo the track 3.
+	/// sheet.retain_tracks(|track| track == 3);
+	/// ```
+	pub fn retain_tracks<F: FnMut(u16) -> bool>(&mut self, mut f: F) {
+		for (tick, m) in self.0.iter_mut().enumerate() {
+			self.1.retain(tick as u32, m, |track, _| f(track));
+		}
+	}
+
+	/// Splits `self` back into one [Sheet] per track, undoing
+	/// [Sheet::parallel].
+	///
+	/// The item at index `i` contains the events originating from the track
+	/// `i`. Every returned [Sheet] is as long as `self`.
+	pub fn into_tracks(self) -> Vec<Self> {
+		let len = self.len();
+		let mut sheets = vec![
+			Self(vec![Moment::default(); len], Tracks::default());
+			self.track_count() as usize
+		];
+
+		for (i, m) in self.0.iter().enumerate() {
+			for (track, e) in self.1.iter(i as u32, m) {
+				sheets[track as usize][i].push(e.clone());
+			}
+		}
+		for (i, sheet) in sheets.iter_mut().enumerate() {
+			sheet.1 = Tracks::single(i as u16, (&*sheet).into());
+		}
+
+		sheets
+	}
+}
+
+impl SparseSheet {
+	// Creates a SparseSheet out of a single track, recording its index as the origin of every event.
+	pub(crate) fn from_track(index: u16, events: &[TrackEvent<'_>]) -> Self {
+		let mut sheet = Self::from(events);
+		sheet.tracks = Tracks::single(index, (&sheet).into());
+		sheet
+	}
+
+	/// Returns the number of tracks `self` was created from.
+	///
+	/// See [Sheet::track_count].
+	pub fn track_count(&self) -> u16 {
+		self.tracks.count()
+	}
+
+	/// Returns the events at `tick`, paired with the index of the track they
+	/// originate from.
+	///
+	/// See [Sheet::iter_tracked].
+	pub fn iter_tracked(&self, tick: u32) -> impl Iterator<Item = (u16, &Event)> + '_ {
+		self.get(tick)
+			.into_iter()
+			.flat_map(move |m| self.tracks.iter(tick, m))
+	}
+
+	/// Returns the name of the given track, if it has one.
+	///
+	/// See [Sheet::track_name].
+	pub fn track_name(&self, track: u16) -> Option<&[u8]> {
+		track_name(self.into(), track)
+	}
+
+	/// Keeps only the events originating from the tracks for which `f` returns
+	/// `true`.
+	///
+	/// See [Sheet::retain_tracks].
+	pub fn retain_tracks<F: FnMut(u16) -> bool>(&mut self, mut f: F) {
+		for (tick, m) in &mut self.moments {
+			self.tracks.retain(*tick, m, |track, _| f(track));
+		}
+		self.moments.retain(|(_, m)| !m.is_empty());
+	}
+
+	/// Splits `self` back into one [SparseSheet] per track, undoing
+	/// [SparseSheet::parallel].
+	///
+	/// See [Sheet::into_tracks].
+	pub fn into_tracks(self) -> Vec<Self> {
+		let mut sheets = vec![
+			Self {
+				moments: Vec::new(),
+				len: self.len,
+				tracks: Tracks::default(),
+			};
+			self.track_count() as usize
+		];
+
+		for (tick, m) in &self.moments {
+			for (track, e) in self.tracks.iter(*tick, m) {
+				let moments = &mut sheets[track as usize].moments;
+				match moments.last_mut() {
+					Some((t, m)) if t == tick => m.push(e.clone()),
+					_ => moments.push((*tick, vec![e.clone()].into())),
+				}
+			}
+		}
+		for (i, sheet) in sheets.iter_mut().enumerate() {
+			sheet.tracks = Tracks::single(i as u16, (&*sheet).into());
+		}
+
+		sheets
+	}
+}
+
+fn track_name(timeline: Timeline<'_>, track: u16) -> Option<&[u8]> {
+	let tracks = timeline.tracks();
+	timeline
+		.iter()
+		.flat_map(|(tick, m)| tracks.iter(tick, m))
+		.find_map(|(t, e)| match e {
+			Event::Text(TextKind::TrackName, name) if t == track => Some(name.as_slice()),
+			_ => None,
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use midly::{MetaMessage, MidiMessage, TrackEventKind};
+
+	use super::*;
+
+	#[test]
+	fn split_tracks() {
+		let track = |name: &'static [u8], key: u8| {
+			vec![
+				TrackEvent {
+					delta: 0.into(),
+					kind: TrackEventKind::Meta(MetaMessage::TrackName(name)),
+				},
+				TrackEvent {
+					delta: u32::from(key).into(),
+					kind: TrackEventKind::Midi {
+						channel: 0.into(),
+						message: MidiMessage::NoteOn {
+							key: key.into(),
+							vel: 64.into(),
+						},
+					},
+				},
+				TrackEvent {
+					delta: 0.into(),
+					kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+				},
+			]
+		};
+		let tracks = vec![track(b"piano", 10), track(b"bass", 10), track(b"drums", 5)];
+
+		let sheet = Sheet::parallel(&tracks);
+		assert_eq!(sheet.track_count(), 3);
+		assert_eq!(sheet.track_name(1), Some(&b"bass"[..]));
+		assert_eq!(
+			sheet.iter_tracked(10).map(|(t, _)| t).collect::<Vec<_>>(),
+			[0, 1]
+		);
+
+		let mut transposed = sheet.clone();
+		transposed.transpose(1, false);
+		assert_eq!(transposed.track_name(2), Some(&b"drums"[..]));
+		// Events added directly belong to the track 0, as do the others of their moment.
+		transposed[10].push(Event::Tempo(500_000));
+		assert!(transposed.iter_tracked(10).all(|(t, _)| t == 0));
+		// So do reordered events, even though the moment keeps its length.
+		let mut reordered = sheet.clone();
+		reordered[10].events.reverse();
+		assert!(reordered.iter_tracked(10).all(|(t, _)| t == 0));
+		assert_eq!(reordered.clone().into_tracks()[0][10].len(), 2);
+		for m in reordered.iter_mut() {
+			m.events.reverse();
+		}
+		assert!(reordered.iter_tracked(0).all(|(t, _)| t == 0));
+
+		let mut muted = sheet.clone();
+		muted.retain_tracks(|t| t != 1);
+		assert_eq!(muted[0].len(), 2);
+		assert_eq!(muted.track_name(1), None);
+
+		let split = sheet.into_tracks();
+		assert_eq!(split.len(), 3);
+		for (i, sh) in split.iter().enumerate() {
+			assert_eq!(sh.len(), 11);
+			assert_eq!(sh.track_count(), i as u16 + 1);
+			assert_eq!(sh[0].len(), 1);
+		}
+		assert_eq!(split[2][5].len(), 1);
+
+		// Tracks without events are kept.
+		let mut with_empty = tracks.clone();
+		with_empty.push(vec![TrackEvent {
+			delta: 0.into(),
+			kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+		}]);
+		let sheet = Sheet::parallel(&with_empty);
+		assert_eq!(sheet.track_count(), 4);
+		assert_eq!(sheet.into_tracks().len(), 4);
+
+		let sparse = SparseSheet::parallel(&tracks);
+		assert_eq!(sparse.track_name(2), Some(&b"drums"[..]));
+		let split = sparse.into_tracks();
+		assert_eq!(split.len(), 3);
+		assert_eq!(Sheet::from(split[2].clone()).into_inner(), {
+			let mut expected = Sheet::from_track(2, &tracks[2]);
+			expected.0.resize(11, Moment::default());
+			expected.into_inner()
+		});
+	}
+}
@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use midly::Timing;
+
+use crate::{Event, KeySignature, TimeSignature, Timeline};
+
+// A span of ticks with a constant tempo.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Segment {
+	// First tick of the segment.
+	tick: u32,
+	// Time at `tick`, in microseconds.
+	micros: f64,
+	tempo: u32,
+	micros_per_tick: f64,
+}
+
+/// Converts between MIDI ticks and wall-clock time for a given track.
+///
+/// A [TempoMap] is built once from a [Sheet](crate::Sheet) or a
+/// [SparseSheet](crate::SparseSheet) and its [Timing]; afterwards every query
+/// runs in logarithmic time and, unlike [Timer::duration](crate::Timer::duration),
+/// does not modify any timer.
+///
+/// # Examples
+/// ```no_run
+/// use std::time::Duration;
+/// use nodi::{midly::Smf, Sheet, TempoMap};
+///
+/// let data = std::fs::read("song.mid")?;
+/// let smf = Smf::parse(&data)?;
+/// let (sheet, _) = Sheet::from_smf(&smf);
+/// let map = TempoMap::new(&sheet, smf.header.timing);
+///
+/// println!("the song is {:?} long", map.duration());
+/// let tick = map.duration_to_tick(Duration::from_secs(30));
+/// println!("at 0:30, we're at tick {} and the tempo is {}", tick, map.tempo_at(tick));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct TempoMap {
+	// Never empty, the first segment starts at tick 0.
+	segments: Vec<Segment>,
+	time_sigs: Vec<(u32, TimeSignature)>,
+	key_sigs: Vec<(u32, KeySignature)>,
+	len: u32,
+}
+
+impl TempoMap {
+	/// The tempo assumed until the first tempo change, as per the MIDI
+	/// specification (120 beats per minute).
+	pub const DEFAULT_TEMPO: u32 = 500_000;
+
+	/// Creates a [TempoMap] from the given track.
+	///
+	/// The tempo is assumed to be [TempoMap::DEFAULT_TEMPO] until the first tempo change.
+	/// If `timing` is [Timing::Timecode], tempo changes are recorded but do not
+	/// affect the timing, since the length of a tick is fixed.
+	pub fn new<'a, S: Into<Timeline<'a>>>(sheet: S, timing: Timing) -> Self {
+		let timeline = sheet.into();
+		let micros_per_tick = |tempo: u32| match timing {
+			Timing::Metrical(tpb) => tempo as f64 / u16::from(tpb) as f64,
+			Timing::Timecode(fps, subframes) => {
+				1_000_000.0 / fps.as_f32() as f64 / subframes as f64
+			}
+		};
+
+		let mut segments = vec![Segment {
+			tick: 0,
+			micros: 0.0,
+			tempo: Self::DEFAULT_TEMPO,
+			micros_per_tick: micros_per_tick(Self::DEFAULT_TEMPO),
+		}];
+		let mut time_sigs = Vec::new();
+		let mut key_sigs = Vec::new();
+
+		for (tick, moment) in timeline {
+			for e in &moment.events {
+				match e {
+					Event::Tempo(tempo) => {
+						let last = segments.last_mut().unwrap();
+						let seg = Segment {
+							tick,
+							micros: last.micros + (tick - last.tick) as f64 * last.micros_per_tick,
+							tempo: *tempo,
+							micros_per_tick: micros_per_tick(*tempo),
+						};
+						if last.tick == tick {
+							*last = seg;
+						} else {
+							segments.push(seg);
+						}
+					}
+					Event::TimeSignature(..) => {
+						time_sigs.push((tick, e.as_time_signature().unwrap()))
+					}
+					Event::KeySignature(..) => key_sigs.push((tick, e.as_key_signature().unwrap())),
+					_ => (),
+				}
+			}
+		}
+
+		Self {
+			segments,
+			time_sigs,
+			key_sigs,
+			len: timeline.len(),
+		}
+	}
+
+	/// Returns the length of the track, in ticks.
+	pub fn len(&self) -> u32 {
+		self.len
+	}
+
+	/// Returns `self.len() == 0`.
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	/// Returns the total duration of the track.
+	pub fn duration(&self) -> Duration {
+		self.tick_to_duration(self.len)
+	}
+
+	// Returns the segment containing the given tick.
+	fn segment_at(&self, tick: u32) -> &Segment {
+		let i = self.segments.partition_point(|s| s.tick <= tick);
+		&self.segments[i - 1]
+	}
+
+	/// Returns the time elapsed from the start of the track until the given
+	/// tick.
+	pub fn tick_to_duration(&self, tick: u32) -> Duration {
+		let seg = self.segment_at(tick);
+		let micros = seg.micros + (tick - seg.tick) as f64 * seg.micros_per_tick;
+		Duration::from_micros(micros as u64)
+	}
+
+	/// Returns the tick that is playing at the given time.
+	///
+	/// The returned value may be past the end of the track.
+	pub fn duration_to_tick(&self, time: Duration) -> u32 {
+		let micros = time.as_micros() as f64;
+		let i = self.segments.partition_point(|s| s.micros <= micros);
+		// There is always a segment starting at time 0.
+		let seg = &self.segments[i.max(1) - 1];
+		if seg.micros_per_tick <= 0.0 {
+			return seg.tick;
+		}
+		// The epsilon compensates for rounding errors, for tick boundaries to round-trip.
+		seg.tick + ((micros - seg.micros) / seg.micros_per_tick + 1e-6) as u32
+	}
+
+	/// Returns the tempo in effect at the given tick, in microseconds per
+	/// beat.
+	pub fn tempo_at(&self, tick: u32) -> u32 {
+		self.segment_at(tick).tempo
+	}
+
+	/// Returns the time signature in effect at the given tick.
+	///
+	/// Returns the default (4/4) time signature if there is no time signature
+	/// event at or before `tick`.
+	pub fn time_signature_at(&self, tick: u32) -> TimeSignature {
+		last_at(&self.time_sigs, tick).unwrap_or_default()
+	}
+
+	/// Returns the key signature in effect at the given tick.
+	///
+	/// Returns the default (C major) key signature if there is no key
+	/// signature event at or before `tick`.
+	pub fn key_signature_at(&self, tick: u32) -> KeySignature {
+		last_at(&self.key_sigs, tick).unwrap_or_default()
+	}
+}
+
+fn last_at<T: Copy>(items: &[(u32, T)], tick: u32) -> Option<T> {
+	match items.partition_point(|(t, _)| *t <= tick) {
+		0 => None,
+		i => Some(items[i - 1].1),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{timers::Ticker, Moment, Sheet, Timer};
+
+	#[test]
+	fn tick_conversions() {
+		let mut sheet = Sheet::new();
+		sheet.extend((0..4000).map(|_| Moment::default()));
+		// 120 BPM until tick 960, then 60 BPM.
+		sheet[960].push(Event::Tempo(1_000_000));
+		sheet[1920].push(Event::TimeSignature(3, 2, 24, 8));
+		let map = TempoMap::new(&sheet, Timing::Metrical(480.into()));
+
+		assert_eq!(map.tick_to_duration(0), Duration::ZERO);
+		assert_eq!(map.tick_to_duration(480), Duration::from_millis(500));
+		assert_eq!(map.tick_to_duration(960), Duration::from_secs(1));
+		assert_eq!(map.tick_to_duration(1440), Duration::from_secs(2));
+		assert_eq!(map.duration_to_tick(Duration::from_millis(500)), 480);
+		assert_eq!(map.duration_to_tick(Duration::from_secs(2)), 1440);
+		assert_eq!(map.tempo_at(959), TempoMap::DEFAULT_TEMPO);
+		assert_eq!(map.tempo_at(960), 1_000_000);
+		assert_eq!(map.time_signature_at(1919), TimeSignature::default());
+		assert_eq!(map.time_signature_at(1920).numerator, 3);
+
+		// Agrees with what a Ticker would sleep.
+		let mut ticker = Ticker::with_initial_tempo(480, TempoMap::DEFAULT_TEMPO);
+		let mut expected = Duration::ZERO;
+		let mut last = 0;
+		for (tick, moment) in sheet.timeline() {
+			expected += ticker.sleep_duration_without_readjustment(tick - last);
+			last = tick;
+			if let Some(Event::Tempo(t)) = moment.first() {
+				ticker.change_tempo(*t);
+			}
+			assert_eq!(map.tick_to_duration(tick), expected);
+		}
+	}
+}
@@ -0,0 +1,54 @@
+//! Helpers shared by the unit tests.
+
+use midly::MidiMessage;
+
+use crate::{Connection, MidiEvent};
+
+/// A [Connection] that keeps every event it plays.
+#[derive(Default)]
+pub(crate) struct Capture(pub(crate) Vec<MidiEvent>);
+
+impl Connection for Capture {
+	fn play(&mut self, event: MidiEvent) -> bool {
+		self.0.push(event);
+		true
+	}
+}
+
+pub(crate) fn midi(ch: u8, message: MidiMessage) -> MidiEvent {
+	MidiEvent {
+		channel: ch.into(),
+		message,
+	}
+}
+
+pub(crate) fn on(ch: u8, key: u8, vel: u8) -> MidiEvent {
+	midi(
+		ch,
+		MidiMessage::NoteOn {
+			key: key.into(),
+			vel: vel.into(),
+		},
+	)
+}
+
+// With the velocity used by ActiveNotes when releasing notes.
+pub(crate) fn off(ch: u8, key: u8) -> MidiEvent {
+	midi(
+		ch,
+		MidiMessage::NoteOff {
+			key: key.into(),
+			vel: 64.into(),
+		},
+	)
+}
+
+pub(crate) fn controller(ch: u8, controller: u8, value: u8) -> MidiEvent {
+	midi(
+		ch,
+		MidiMessage::Controller {
+			controller: controller.into(),
+			value: value.into(),
+		},
+	)
+}
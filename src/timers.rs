@@ -1,5 +1,8 @@
 #![doc = include_str!("doc_timers.md")]
 
+mod external;
+mod virtual_clock;
+
 use std::{
 	convert::TryFrom,
 	fmt,
@@ -12,8 +15,13 @@ use midly::Timing;
 
 use crate::{Event, Moment, Timer};
 
+pub use external::{ClockMessage, ClockSender, ExternalClock};
+pub use virtual_clock::{VirtualClock, VirtualTime};
+
 /// An error that might arise while converting [Timing] to a [Ticker] or
 /// [FixedTempo].
+///
+/// It converts into [Error::UnsupportedTiming](crate::Error::UnsupportedTiming).
 pub struct TimeFormatError;
 
 impl std::error::Error for TimeFormatError {}
@@ -75,6 +83,7 @@ impl Ticker {
 			last_instant: self.last_instant,
 			ticks_per_beat: self.ticks_per_beat,
 			pause,
+			paused: false,
 		}
 	}
 
@@ -97,6 +106,18 @@ impl Timer for Ticker {
 		self.micros_per_tick = micros_per_tick;
 	}
 
+	fn tempo(&self) -> Option<u32> {
+		Some((self.micros_per_tick * self.ticks_per_beat as f64).round() as u32)
+	}
+
+	fn reset(&mut self) {
+		self.last_instant = None;
+	}
+
+	fn nominal_duration(&mut self, n_ticks: u32) -> Duration {
+		self.sleep_duration_without_readjustment(n_ticks)
+	}
+
 	fn sleep_duration(&mut self, n_ticks: u32) -> Duration {
 		let mut t = self.sleep_duration_without_readjustment(n_ticks);
 
@@ -151,11 +172,16 @@ impl TryFrom<Timing> for Ticker {
 /// This type corresponds to [Timing::Timecode] and can be converted using
 /// [TryFrom::try_from]. Try to avoid using this timer because it's not tested
 /// (I couldn't find any MIDI files using [Timing::Timecode]).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct FixedTempo(pub u64);
 
 impl TryFrom<Timing> for FixedTempo {
 	type Error = TimeFormatError;
 
+	/// Tries to create a [FixedTempo] from the provided [Timing].
+	///
+	/// # Errors
+	/// Will return an error if the given [Timing] is not [Timing::Timecode].
 	fn try_from(t: Timing) -> Result<Self, Self::Error> {
 		if let Timing::Timecode(fps, frame) = t {
 			let micros = 1_000_000.0 / fps.as_f32() / frame as f32;
@@ -168,20 +194,93 @@ impl TryFrom<Timing> for FixedTempo {
 
 impl Timer for FixedTempo {
 	fn sleep_duration(&mut self, n_ticks: u32) -> Duration {
-		Duration::from_millis(self.0 * n_ticks as u64)
+		Duration::from_micros(self.0 * n_ticks as u64)
 	}
 
 	/// This function does nothing.
 	fn change_tempo(&mut self, _: u32) {}
 }
 
+/// A [Timer] suitable for any [Timing]; either a [Ticker] or a [FixedTempo].
+///
+/// This is what [Sheet::from_smf](crate::Sheet::from_smf) returns, and it can
+/// also be created with [From]\<[Timing]\>.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AnyTimer {
+	/// Used for [Timing::Metrical].
+	Ticker(Ticker),
+	/// Used for [Timing::Timecode].
+	FixedTempo(FixedTempo),
+}
+
+impl From<Timing> for AnyTimer {
+	fn from(t: Timing) -> Self {
+		match t {
+			Timing::Metrical(n) => Self::Ticker(Ticker::new(u16::from(n))),
+			Timing::Timecode(..) => Self::FixedTempo(FixedTempo::try_from(t).unwrap()),
+		}
+	}
+}
+
+impl Timer for AnyTimer {
+	fn sleep_duration(&mut self, n_ticks: u32) -> Duration {
+		match self {
+			Self::Ticker(t) => t.sleep_duration(n_ticks),
+			Self::FixedTempo(t) => t.sleep_duration(n_ticks),
+		}
+	}
+
+	fn change_tempo(&mut self, tempo: u32) {
+		match self {
+			Self::Ticker(t) => t.change_tempo(tempo),
+			Self::FixedTempo(t) => t.change_tempo(tempo),
+		}
+	}
+
+	fn tempo(&self) -> Option<u32> {
+		match self {
+			Self::Ticker(t) => t.tempo(),
+			Self::FixedTempo(t) => t.tempo(),
+		}
+	}
+
+	fn reset(&mut self) {
+		match self {
+			Self::Ticker(t) => t.reset(),
+			Self::FixedTempo(t) => t.reset(),
+		}
+	}
+
+	fn nominal_duration(&mut self, n_ticks: u32) -> Duration {
+		match self {
+			Self::Ticker(t) => t.nominal_duration(n_ticks),
+			Self::FixedTempo(t) => t.nominal_duration(n_ticks),
+		}
+	}
+
+	fn sleep(&mut self, n_ticks: u32) {
+		match self {
+			Self::Ticker(t) => t.sleep(n_ticks),
+			Self::FixedTempo(t) => t.sleep(n_ticks),
+		}
+	}
+
+	fn duration(&mut self, moments: &[Moment]) -> Duration {
+		match self {
+			Self::Ticker(t) => t.duration(moments),
+			Self::FixedTempo(t) => t.duration(moments),
+		}
+	}
+}
+
 /// A [Timer] that lets you toggle playback.
 ///
 /// This type works exactly like [Ticker], but it checks for messages
 /// on a [Receiver] and toggles playback if there is one.
 ///
 /// Sending a message to [self.pause] will pause the thread until another
-/// message is received.
+/// message is received. The [Player](crate::Player) releases the sounding
+/// notes when pausing.
 ///
 /// # Notes
 /// Using [Ticker] is recommended over this, mainly because there is the
@@ -201,6 +300,8 @@ pub struct ControlTicker {
 	pub speed: f32,
 	/// Messages to this channel will toggle playback.
 	pub pause: Receiver<()>,
+	// A pause message was received by will_pause, the next sleep pauses.
+	paused: bool,
 }
 
 impl ControlTicker {
@@ -212,6 +313,7 @@ impl ControlTicker {
 		Self {
 			ticks_per_beat,
 			pause,
+			paused: false,
 			last_instant: None,
 			micros_per_tick: 0.0,
 			speed: 1.0,
@@ -254,6 +356,25 @@ impl Timer for ControlTicker {
 		self.micros_per_tick = micros_per_tick;
 	}
 
+	fn tempo(&self) -> Option<u32> {
+		Some((self.micros_per_tick * self.ticks_per_beat as f64).round() as u32)
+	}
+
+	fn reset(&mut self) {
+		self.last_instant = None;
+	}
+
+	fn nominal_duration(&mut self, n_ticks: u32) -> Duration {
+		self.sleep_duration_without_readjustment(n_ticks)
+	}
+
+	/// Returns `true` if there is a message on [self.pause], so that the
+	/// [Player](crate::Player) releases the sounding notes before pausing.
+	fn will_pause(&mut self) -> bool {
+		self.paused |= self.pause.try_recv().is_ok();
+		self.paused
+	}
+
 	fn sleep_duration(&mut self, n_ticks: u32) -> Duration {
 		let mut t = self.sleep_duration_without_readjustment(n_ticks);
 
@@ -273,7 +394,7 @@ impl Timer for ControlTicker {
 	/// continuing with the sleep.
 	fn sleep(&mut self, n_ticks: u32) {
 		// Check if we're supposed to be paused.
-		if self.pause.try_recv().is_ok() {
+		if std::mem::take(&mut self.paused) || self.pause.try_recv().is_ok() {
 			// Wait for the next message in order to continue, continue.
 			self.pause
 				.recv()
@@ -349,3 +470,71 @@ fn spin_lock(t: Duration) {
 
 #[cfg(not(any(doc, test, feature = "hybrid-sleep")))]
 pub(crate) use thread::sleep;
+
+#[cfg(test)]
+mod tests {
+	use std::sync::mpsc;
+
+	use midly::Fps;
+
+	use super::*;
+	use crate::{
+		test_support::{controller, off, on, Capture},
+		Connection, MidiEvent, Player, Sheet,
+	};
+
+	#[test]
+	fn fixed_tempo() {
+		// 25 frames per second, 40 ticks per frame: a tick lasts a millisecond.
+		let mut timer = FixedTempo::try_from(Timing::Timecode(Fps::Fps25, 40)).unwrap();
+		assert_eq!(timer, FixedTempo(1000));
+		assert_eq!(timer.sleep_duration(3), Duration::from_millis(3));
+	}
+
+	#[test]
+	fn any_timer() {
+		let mut metrical = AnyTimer::from(Timing::Metrical(480.into()));
+		metrical.change_tempo(480_000);
+		assert_eq!(metrical.nominal_duration(3), Duration::from_millis(3));
+
+		let mut timecode = AnyTimer::from(Timing::Timecode(Fps::Fps25, 40));
+		timecode.change_tempo(1_000_000);
+		assert_eq!(timecode.nominal_duration(3), Duration::from_millis(3));
+	}
+
+	#[test]
+	fn control_ticker_pause() {
+		// Pauses and resumes right away once the note is played.
+		struct Pauser(Capture, mpsc::Sender<()>);
+
+		impl Connection for Pauser {
+			fn play(&mut self, event: MidiEvent) -> bool {
+				if self.0 .0.is_empty() {
+					self.1.send(()).unwrap();
+					self.1.send(()).unwrap();
+				}
+				self.0.play(event)
+			}
+		}
+
+		let mut sheet = Sheet::new();
+		sheet.extend((0..3).map(|_| Default::default()));
+		sheet[0].push(Event::Midi(on(0, 60, 100)));
+		sheet[2].push(Event::Midi(off(0, 60)));
+
+		let (sender, pause) = mpsc::channel();
+		let timer = ControlTicker::with_initial_tempo(1, 1000, pause);
+		let mut player = Player::new(timer, Pauser(Capture::default(), sender));
+		assert!(player.play(&sheet));
+		// The note is released before pausing.
+		assert_eq!(
+			player.con.0 .0,
+			[
+				on(0, 60, 100),
+				off(0, 60),
+				controller(0, 123, 0),
+				off(0, 60)
+			]
+		);
+	}
+}
@@ -0,0 +1,387 @@
+use std::{
+	convert::TryFrom,
+	mem,
+	sync::mpsc::{self, Receiver, Sender},
+	time::{Duration, Instant},
+};
+
+use midly::live::{LiveEvent, SystemCommon, SystemRealtime};
+
+use super::sleep;
+use crate::{Error, Timer};
+
+/// A MIDI message that drives an [ExternalClock].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ClockMessage {
+	/// A clock pulse; the master sends 24 of them per beat (quarter note).
+	TimingClock,
+	/// Start playing from the beginning.
+	Start,
+	/// Resume playing from the current song position.
+	Continue,
+	/// Stop playing.
+	Stop,
+	/// Set the song position, in sixteenth notes.
+	SongPosition(u16),
+}
+
+impl TryFrom<LiveEvent<'_>> for ClockMessage {
+	type Error = Error;
+
+	/// Tries to create [Self] from a [LiveEvent].
+	///
+	/// # Errors
+	/// Will return [Error::UnsupportedEvent] if the given [LiveEvent] is not
+	/// one of the messages of [ClockMessage].
+	fn try_from(event: LiveEvent<'_>) -> Result<Self, Self::Error> {
+		Ok(match event {
+			LiveEvent::Realtime(SystemRealtime::TimingClock) => Self::TimingClock,
+			LiveEvent::Realtime(SystemRealtime::Start) => Self::Start,
+			LiveEvent::Realtime(SystemRealtime::Continue) => Self::Continue,
+			LiveEvent::Realtime(SystemRealtime::Stop) => Self::Stop,
+			LiveEvent::Common(SystemCommon::SongPosition(pos)) => Self::SongPosition(pos.as_int()),
+			_ => return Err(Error::UnsupportedEvent),
+		})
+	}
+}
+
+/// The sending half of an [ExternalClock], used to feed it the messages of
+/// the master device.
+///
+/// This `struct` is created by [ExternalClock::new]; it can be cloned and
+/// sent to other threads, for example to a MIDI input callback.
+#[derive(Debug, Clone)]
+pub struct ClockSender(Sender<(Instant, ClockMessage)>);
+
+impl ClockSender {
+	/// Sends a message, received just now.
+	///
+	/// Returns `false` if the [ExternalClock] has been dropped.
+	pub fn send(&self, msg: ClockMessage) -> bool {
+		self.send_at(Instant::now(), msg)
+	}
+
+	/// Sends a message, received at the given time.
+	///
+	/// Use this if your MIDI input provides timestamps, they make the tempo
+	/// estimation more accurate.
+	///
+	/// Returns `false` if the [ExternalClock] has been dropped.
+	pub fn send_at(&self, at: Instant, msg: ClockMessage) -> bool {
+		self.0.send((at, msg)).is_ok()
+	}
+
+	/// Parses and sends a raw MIDI message, received just now.
+	///
+	/// Messages that are not relevant to the clock are ignored.
+	///
+	/// Returns `false` if the [ExternalClock] has been dropped.
+	pub fn send_bytes(&self, data: &[u8]) -> bool {
+		match LiveEvent::parse(data).map(ClockMessage::try_from) {
+			Ok(Ok(msg)) => self.send(msg),
+			_ => true,
+		}
+	}
+}
+
+/// A [Timer] that follows an external MIDI clock.
+///
+/// Instead of using a tempo, this timer waits for the clock pulses
+/// ([SystemRealtime::TimingClock]) of a master device: every
+/// [sleep](Timer::sleep) blocks until the master has advanced by the requested
+/// number of ticks. The master's transport messages are followed too; the
+/// timer does not advance after a [Stop](ClockMessage::Stop) until a
+/// [Start](ClockMessage::Start) or a [Continue](ClockMessage::Continue).
+///
+/// The messages are fed through the [ClockSender] returned by
+/// [ExternalClock::new].
+///
+/// # Notes
+/// - The tempo of the master is estimated from the time between pulses, it's
+///   what [Timer::sleep_duration] uses. Before any pulse arrives, the tempo
+///   set by [Timer::change_tempo] is used instead.
+/// - Song position changes (see [ExternalClock::song_position]) make
+///   [Player::play] and its variants jump to the new position, see
+///   [Timer::take_seek].
+/// - If the [ClockSender] and all of its clones are dropped, the timer falls
+///   back to sleeping with the estimated tempo.
+/// - This timer is meant to be used with [Player::play] and its variants;
+///   [Player::spawn] does its own timing and only relies on the estimated
+///   tempo.
+///
+/// # Examples
+/// ```no_run
+/// use nodi::timers::ExternalClock;
+///
+/// let (clock, sender) = ExternalClock::new(480);
+/// // Feed it from a MIDI input, for example in a midir callback:
+/// // move |_stamp, bytes, _| { sender.send_bytes(bytes); }
+/// ```
+///
+/// [Player]: crate::Player
+/// [Player::play]: crate::Player::play
+/// [Player::spawn]: crate::Player::spawn
+#[derive(Debug)]
+pub struct ExternalClock {
+	ticks_per_beat: u16,
+	input: Receiver<(Instant, ClockMessage)>,
+	running: bool,
+	// Ticks the master advanced and that are not slept yet.
+	credit: f64,
+	song_position: f64,
+	// Whether the song position was set since the last call to take_seek.
+	relocated: bool,
+	last_pulse: Option<Instant>,
+	// The estimated length of a tick, 0 if unknown.
+	micros_per_tick: f64,
+	// Whether `micros_per_tick` comes from the pulses rather than from change_tempo.
+	estimated: bool,
+	/// How much a new pulse affects the estimated tempo, between `0.0` and
+	/// `1.0`. Lower values make the estimation smoother but slower to follow
+	/// tempo changes.
+	///
+	/// The default value is `0.1`.
+	pub smoothing: f64,
+}
+
+impl ExternalClock {
+	/// Creates an [ExternalClock] with the given ticks-per-beat, along with
+	/// the [ClockSender] to feed it with.
+	///
+	/// The timer waits for a [Start](ClockMessage::Start) or a
+	/// [Continue](ClockMessage::Continue) before advancing.
+	pub fn new(ticks_per_beat: u16) -> (Self, ClockSender) {
+		let (sender, input) = mpsc::channel();
+		let clock = Self {
+			ticks_per_beat,
+			input,
+			running: false,
+			credit: 0.0,
+			song_position: 0.0,
+			relocated: false,
+			last_pulse: None,
+			micros_per_tick: 0.0,
+			estimated: false,
+			smoothing: 0.1,
+		};
+		(clock, ClockSender(sender))
+	}
+
+	/// Returns `true` if the master is playing.
+	pub fn is_running(&self) -> bool {
+		self.running
+	}
+
+	/// Returns the position of the master, in ticks.
+	pub fn song_position(&self) -> u32 {
+		self.song_position as u32
+	}
+
+	/// Returns the estimated tempo of the master in microseconds per beat, if
+	/// known.
+	pub fn tempo(&self) -> Option<u32> {
+		if self.micros_per_tick > 0.0 {
+			Some((self.micros_per_tick * self.ticks_per_beat as f64).round() as u32)
+		} else {
+			None
+		}
+	}
+
+	/// Processes the messages received so far, without blocking.
+	pub fn update(&mut self) {
+		while let Ok((at, msg)) = self.input.try_recv() {
+			self.process(at, msg);
+		}
+	}
+
+	fn ticks_per_pulse(&self) -> f64 {
+		self.ticks_per_beat as f64 / 24.0
+	}
+
+	fn process(&mut self, at: Instant, msg: ClockMessage) {
+		match msg {
+			ClockMessage::TimingClock => {
+				if self.running {
+					self.credit += self.ticks_per_pulse();
+					self.song_position += self.ticks_per_pulse();
+				}
+				if let Some(last) = self.last_pulse {
+					let sample = at.saturating_duration_since(last).as_micros() as f64
+						/ self.ticks_per_pulse();
+					self.micros_per_tick = if self.estimated {
+						self.micros_per_tick + self.smoothing * (sample - self.micros_per_tick)
+					} else {
+						sample
+					};
+					self.estimated = true;
+				}
+				self.last_pulse = Some(at);
+			}
+			ClockMessage::Start => {
+				self.running = true;
+				self.credit = 0.0;
+				// Restarting from the top mid-song relocates the player.
+				self.relocated |= self.song_position != 0.0;
+				self.song_position = 0.0;
+			}
+			ClockMessage::Continue => self.running = true,
+			ClockMessage::Stop => {
+				self.running = false;
+				// The pulses may stop too, the next interval would be meaningless.
+				self.last_pulse = None;
+			}
+			ClockMessage::SongPosition(pos) => {
+				self.song_position = pos as f64 * self.ticks_per_beat as f64 / 4.0;
+				// The ticks to sleep are counted from the new position.
+				self.credit = 0.0;
+				self.relocated = true;
+			}
+		}
+	}
+}
+
+impl Timer for ExternalClock {
+	/// Returns the duration of `n_ticks` at the estimated tempo.
+	fn sleep_duration(&mut self, n_ticks: u32) -> Duration {
+		Duration::from_micros((self.micros_per_tick * n_ticks as f64) as u64)
+	}
+
+	/// Sets the estimated tempo, unless it's already estimated from the clock
+	/// pulses.
+	fn change_tempo(&mut self, tempo: u32) {
+		if !self.estimated {
+			self.micros_per_tick = tempo as f64 / self.ticks_per_beat as f64;
+		}
+	}
+
+	/// Returns the estimated tempo, see [ExternalClock::tempo].
+	fn tempo(&self) -> Option<u32> {
+		Self::tempo(self)
+	}
+
+	/// Returns the position of the master if it was relocated with a
+	/// [SongPosition](ClockMessage::SongPosition) message, or restarted from
+	/// the beginning with a [Start](ClockMessage::Start) message, since the
+	/// last call.
+	fn take_seek(&mut self) -> Option<u32> {
+		mem::take(&mut self.relocated).then(|| self.song_position())
+	}
+
+	/// Blocks until the master advances by `n_ticks`.
+	fn sleep(&mut self, n_ticks: u32) {
+		let n = n_ticks as f64;
+		// Tolerates rounding errors when the ticks per beat is not a multiple of 24.
+		while self.credit + 1e-6 < n {
+			match self.input.recv() {
+				Ok((at, msg)) => self.process(at, msg),
+				Err(_) => {
+					let left = ((n - self.credit) * self.micros_per_tick) as u64;
+					self.credit = n;
+					sleep(Duration::from_micros(left));
+				}
+			}
+		}
+		self.credit = (self.credit - n).max(0.0);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use midly::MidiMessage;
+
+	use super::*;
+	use crate::{
+		test_support::{controller, midi, off, on, Capture},
+		Event, Player, SparseSheet,
+	};
+
+	#[test]
+	fn external_clock() {
+		let (mut clock, sender) = ExternalClock::new(96);
+		let start = Instant::now();
+		let pulse = Duration::from_millis(20);
+
+		// Ignored, the master is not running.
+		sender.send_at(start, ClockMessage::TimingClock);
+		sender.send_at(start, ClockMessage::Start);
+		for i in 1..=48 {
+			sender.send_at(start + pulse * i, ClockMessage::TimingClock);
+		}
+		// 48 pulses, 2 beats.
+		clock.sleep(96);
+		clock.sleep(96);
+		assert_eq!(clock.song_position(), 192);
+		assert_eq!(clock.tempo(), Some(480_000));
+		assert_eq!(clock.sleep_duration(96), Duration::from_millis(480));
+
+		sender.send(ClockMessage::Stop);
+		sender.send(ClockMessage::TimingClock);
+		sender.send(ClockMessage::SongPosition(8));
+		clock.update();
+		assert!(!clock.is_running());
+		assert_eq!(clock.song_position(), 192);
+		assert_eq!(clock.tempo(), Some(480_000));
+
+		assert!(ClockMessage::try_from(LiveEvent::Realtime(SystemRealtime::Reset)).is_err());
+		drop(sender);
+		// Falls back to the estimated tempo.
+		let now = Instant::now();
+		clock.sleep(4);
+		assert!(now.elapsed() >= Duration::from_millis(15));
+	}
+
+	#[test]
+	fn restart() {
+		let (mut clock, sender) = ExternalClock::new(24);
+		sender.send(ClockMessage::Start);
+		clock.update();
+		// Starting from the beginning is not a relocation.
+		assert_eq!(clock.take_seek(), None);
+
+		for _ in 0..30 {
+			sender.send(ClockMessage::TimingClock);
+		}
+		sender.send(ClockMessage::Stop);
+		sender.send(ClockMessage::Start);
+		clock.update();
+		assert_eq!(clock.take_seek(), Some(0));
+		assert_eq!(clock.take_seek(), None);
+	}
+
+	#[test]
+	fn song_position() {
+		let on = |key: u8| on(0, key, 100);
+		let program = midi(0, MidiMessage::ProgramChange { program: 5.into() });
+		let mut sheet = SparseSheet::new();
+		sheet.insert(0, Event::Midi(on(1)));
+		sheet.insert(10, Event::Midi(on(2)));
+		sheet.insert(20, Event::Midi(program));
+		sheet.insert(50, Event::Midi(on(3)));
+		sheet.insert(60, Event::Midi(on(4)));
+
+		// A tick per pulse.
+		let (clock, sender) = ExternalClock::new(24);
+		sender.send(ClockMessage::Start);
+		for _ in 0..5 {
+			sender.send(ClockMessage::TimingClock);
+		}
+		sender.send(ClockMessage::Stop);
+		// The eighth sixteenth note is the tick 48.
+		sender.send(ClockMessage::SongPosition(8));
+		sender.send(ClockMessage::Continue);
+		for _ in 0..12 {
+			sender.send(ClockMessage::TimingClock);
+		}
+		drop(sender);
+
+		let mut player = Player::new(clock, Capture::default());
+		assert!(player.play(&sheet));
+		// The sleep to the tick 10 ends at the tick 58: the note is released
+		// and the program is chased.
+		let release = |key: u8| [off(0, key), controller(0, 123, 0)];
+		assert_eq!(
+			player.con.0,
+			[&[on(1)], &release(1)[..], &[program, on(4)], &release(4)].concat()
+		);
+	}
+}
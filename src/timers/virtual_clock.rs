@@ -0,0 +1,122 @@
+use std::{
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use crate::{Moment, SparseSheet, Timer};
+
+/// The current time of a [VirtualClock], shared with other parts of the
+/// program.
+///
+/// This `struct` is obtained with [VirtualClock::time]; it can be cloned and
+/// sent to other threads. Every clone reads the same time.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualTime(Arc<AtomicU64>);
+
+impl VirtualTime {
+	/// Returns the time elapsed on the [VirtualClock] since it was created.
+	pub fn get(&self) -> Duration {
+		Duration::from_nanos(self.0.load(Ordering::Relaxed))
+	}
+
+	fn advance(&self, t: Duration) {
+		self.0.fetch_add(t.as_nanos() as u64, Ordering::Relaxed);
+	}
+}
+
+/// A [Timer] that never sleeps, but advances a virtual clock instead.
+///
+/// The durations are computed by the wrapped timer, so a
+/// `VirtualClock<Ticker>` follows the tempo changes exactly like a [Ticker]
+/// would, only instantly. This makes playback deterministic, which is useful
+/// for tests and for rendering a track to timestamped events; see
+/// [Recorder](crate::Recorder).
+///
+/// # Examples
+/// ```
+/// use std::time::Duration;
+/// use nodi::{timers::{Ticker, VirtualClock}, Timer};
+///
+/// // 120 BPM, 480 ticks per beat.
+/// let mut clock = VirtualClock::new(Ticker::with_initial_tempo(480, 500_000));
+/// clock.sleep(960);
+/// assert_eq!(clock.now(), Duration::from_secs(1));
+/// ```
+///
+/// [Ticker]: super::Ticker
+#[derive(Debug, Clone)]
+pub struct VirtualClock<T: Timer> {
+	timer: T,
+	time: VirtualTime,
+}
+
+impl<T: Timer> VirtualClock<T> {
+	/// Creates a [VirtualClock] that computes the durations with `timer`,
+	/// starting at zero.
+	pub fn new(timer: T) -> Self {
+		Self {
+			timer,
+			time: VirtualTime::default(),
+		}
+	}
+
+	/// Returns the time elapsed on this clock since it was created.
+	pub fn now(&self) -> Duration {
+		self.time.get()
+	}
+
+	/// Returns a handle to the time of this clock, to read it from elsewhere,
+	/// for example from a [Connection](crate::Connection).
+	pub fn time(&self) -> VirtualTime {
+		self.time.clone()
+	}
+}
+
+impl<T: Timer> Timer for VirtualClock<T> {
+	fn sleep_duration(&mut self, n_ticks: u32) -> Duration {
+		// The wrapped timer must not adjust the duration to the real clock.
+		self.timer.reset();
+		self.timer.sleep_duration(n_ticks)
+	}
+
+	fn change_tempo(&mut self, tempo: u32) {
+		self.timer.change_tempo(tempo);
+	}
+
+	fn tempo(&self) -> Option<u32> {
+		self.timer.tempo()
+	}
+
+	fn reset(&mut self) {
+		self.timer.reset();
+	}
+
+	fn nominal_duration(&mut self, n_ticks: u32) -> Duration {
+		self.timer.nominal_duration(n_ticks)
+	}
+
+	fn take_seek(&mut self) -> Option<u32> {
+		self.timer.take_seek()
+	}
+
+	fn will_pause(&mut self) -> bool {
+		self.timer.will_pause()
+	}
+
+	/// Advances the clock by `n_ticks` without sleeping.
+	fn sleep(&mut self, n_ticks: u32) {
+		let t = self.sleep_duration(n_ticks);
+		self.time.advance(t);
+	}
+
+	fn duration(&mut self, moments: &[Moment]) -> Duration {
+		self.timer.duration(moments)
+	}
+
+	fn sparse_duration(&mut self, sheet: &SparseSheet) -> Duration {
+		self.timer.sparse_duration(sheet)
+	}
+}